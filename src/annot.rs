@@ -8,6 +8,13 @@
 //!
 //! Furthermore we may have split the "group" across several lines
 //! during our RTF snipperation. So we have to be quite hacky here.
+//!
+//! Inline footnotes (`{\Scrv_fn ... \text=...\end_Scrv_fn}`) use the
+//! same syntax but are anchored at a point in the paragraph, so rather
+//! than splitting content around them we cut them out and record
+//! where they were.
+use crate::markup;
+use crate::tag;
 
 /// Adapted iterator that strips annotations from the underlying iterator
 pub fn skip_annotations<T>(source: T) -> AnnotationAdapter<T>
@@ -25,6 +32,58 @@ where
     AnnotationAdapter::new(source, false, true)
 }
 
+/// Adapted iterator that keeps annotations in place, replacing their
+/// syntax with the marks `markup` renders them from
+pub fn mark_annotations<T>(source: T) -> impl Iterator<Item = String>
+where
+    T: Iterator<Item = String>,
+{
+    source.scan(false, |in_annotation, line| {
+        let mut marked = String::new();
+        let mut rest = line.as_str();
+        // an annotation spanning paragraphs is closed at the end of
+        // each and reopened at the start of the next
+        if *in_annotation {
+            marked.push(markup::ANNOTATION_OPEN);
+        }
+        loop {
+            if *in_annotation {
+                match rest.find(CLOSE) {
+                    Some(idx) => {
+                        marked.push_str(&rest[..idx]);
+                        marked.push(markup::ANNOTATION_CLOSE);
+                        rest = &rest[(idx + CLOSE.len())..];
+                        *in_annotation = false;
+                    }
+                    None => {
+                        marked.push_str(rest);
+                        marked.push(markup::ANNOTATION_CLOSE);
+                        break;
+                    }
+                }
+            } else {
+                match rest.find(OPEN) {
+                    Some(start) => {
+                        marked.push_str(&rest[..start]);
+                        let after = &rest[start..];
+                        rest = match after.find(OPEN_END) {
+                            Some(end) => &after[(end + OPEN_END.len())..],
+                            None => &after[OPEN.len()..],
+                        };
+                        marked.push(markup::ANNOTATION_OPEN);
+                        *in_annotation = true;
+                    }
+                    None => {
+                        marked.push_str(rest);
+                        break;
+                    }
+                }
+            }
+        }
+        Some(marked)
+    })
+}
+
 /// Adapts an rtf::ParagraphIterator to remove or retain annotations.
 pub struct AnnotationAdapter<T>
 where
@@ -67,7 +126,7 @@ impl<T: Iterator<Item = String>> AnnotationAdapter<T> {
                 Some(idx) => {
                     self.in_annotation = false;
                     self.source
-                        .put_back((&line[(idx + CLOSE.len())..]).to_string());
+                        .put_back(line[(idx + CLOSE.len())..].to_string());
                     &line[..idx]
                 }
                 None => line,
@@ -86,7 +145,7 @@ impl<T: Iterator<Item = String>> AnnotationAdapter<T> {
                         .expect("Unsupported: annotation split open across lines");
                     self.in_annotation = true;
                     self.source
-                        .put_back((&line[(end + OPEN_END.len())..]).to_string());
+                        .put_back(line[(end + OPEN_END.len())..].to_string());
                     &line[..start]
                 }
                 None => line,
@@ -101,6 +160,156 @@ impl<T: Iterator<Item = String>> AnnotationAdapter<T> {
     }
 }
 
+/// An inline footnote cut out of item content
+#[derive(Debug, Clone, PartialEq)]
+pub struct Footnote {
+    /// Number of the footnote within the item, from 1
+    pub number: usize,
+    /// Index of the (output) paragraph containing the anchor
+    pub paragraph: usize,
+    /// Character offset of the anchor in the (output) paragraph once
+    /// Scrivener's style tags and any marks are stripped
+    pub offset: usize,
+    /// Footnote text, paragraphs separated by newlines
+    pub text: String,
+}
+
+impl Footnote {
+    /// Marker left in content in place of the footnote
+    pub fn marker(&self) -> String {
+        format!("[{}]", self.number)
+    }
+}
+
+const FN_OPEN: &str = r#"{\Scrv_fn"#;
+const FN_CLOSE: &str = r#"\end_Scrv_fn}"#;
+
+/// Render footnote text in inline footnote syntax, for splicing into
+/// content
+pub fn inline_footnote(text: &str) -> String {
+    format!("{} {}{}{}", FN_OPEN, OPEN_END, text, FN_CLOSE)
+}
+
+/// Adapts a paragraph iterator to cut out inline footnotes, dropping
+/// them or leaving numbered markers (e.g. "[1]") in their place.
+///
+/// The footnotes removed are accumulated and available once the
+/// adapter is exhausted.
+pub struct FootnoteAdapter<T>
+where
+    T: Iterator<Item = String>,
+{
+    /// Line source
+    source: T,
+    /// Whether to leave numbered markers in content
+    markers: bool,
+    /// Footnote left open at the end of the last line
+    open: Option<Footnote>,
+    /// Footnotes completed so far
+    footnotes: Vec<Footnote>,
+    /// Number of paragraphs output so far
+    paragraph: usize,
+}
+
+impl<T: Iterator<Item = String>> FootnoteAdapter<T> {
+    /// Construct a footnote-cutting iterator, optionally leaving
+    /// numbered markers in content
+    pub fn new(source: T, markers: bool) -> Self {
+        FootnoteAdapter {
+            source,
+            markers,
+            open: None,
+            footnotes: vec![],
+            paragraph: 0,
+        }
+    }
+
+    /// Consume the rest of the content and return all the footnotes
+    pub fn into_footnotes(mut self) -> Vec<Footnote> {
+        self.take_footnotes()
+    }
+
+    /// Consume the rest of the content and take all the footnotes,
+    /// leaving the adapter empty
+    pub fn take_footnotes(&mut self) -> Vec<Footnote> {
+        self.by_ref().for_each(drop);
+        self.footnotes.extend(self.open.take());
+        std::mem::take(&mut self.footnotes)
+    }
+
+    /// Cut footnotes out of a line, returning the remaining content
+    fn cut(&mut self, line: &str) -> String {
+        let mut content = String::new();
+        let mut rest = line;
+        loop {
+            if let Some(ref mut footnote) = self.open {
+                match rest.find(FN_CLOSE) {
+                    Some(idx) => {
+                        footnote.text.push_str(&rest[..idx]);
+                        rest = &rest[(idx + FN_CLOSE.len())..];
+                        self.footnotes.extend(self.open.take());
+                    }
+                    None => {
+                        footnote.text.push_str(rest);
+                        return content;
+                    }
+                }
+            } else {
+                match rest.find(FN_OPEN) {
+                    Some(start) => {
+                        content.push_str(&rest[..start]);
+                        let after = &rest[(start + FN_OPEN.len())..];
+                        rest = match after.find(OPEN_END) {
+                            Some(end) => &after[(end + OPEN_END.len())..],
+                            None => after.trim_start(),
+                        };
+                        let footnote = Footnote {
+                            number: self.footnotes.len() + 1,
+                            paragraph: self.paragraph,
+                            offset: markup::strip_marks(tag::strip_tags(content.clone()))
+                                .chars()
+                                .count(),
+                            text: String::new(),
+                        };
+                        if self.markers {
+                            content.push_str(&footnote.marker());
+                        }
+                        self.open = Some(footnote);
+                    }
+                    None => {
+                        content.push_str(rest);
+                        return content;
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<T> Iterator for FootnoteAdapter<T>
+where
+    T: Iterator<Item = String>,
+{
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        while let Some(line) = self.source.next() {
+            if let Some(ref mut footnote) = self.open {
+                if !footnote.text.is_empty() {
+                    footnote.text.push('\n');
+                }
+            }
+            let content = self.cut(&line);
+            if !content.is_empty() {
+                self.paragraph += 1;
+                return Some(content);
+            }
+        }
+
+        None
+    }
+}
+
 impl<T> Iterator for AnnotationAdapter<T>
 where
     T: Iterator<Item = String>,
@@ -156,4 +365,66 @@ pub mod tests {
         let lines: Vec<_> = AnnotationAdapter::new(source.into_iter(), true, true).collect();
         assert_eq!(lines, &["this is an annotation", "This is normal content."]);
     }
+
+    #[test]
+    pub fn test_mark_annotations() {
+        let source = vec![
+            r#"Before {\Scrv_annot \color={\R=0.1\G=0.4\B=0.2} \text=a note\end_Scrv_annot}after."#
+                .to_string(),
+            r#"{\Scrv_annot \text=spanning"#.to_string(),
+            r#"lines\end_Scrv_annot}Done."#.to_string(),
+        ];
+        let lines: Vec<_> = mark_annotations(source.into_iter()).collect();
+        let (open, close) = (markup::ANNOTATION_OPEN, markup::ANNOTATION_CLOSE);
+        assert_eq!(
+            lines,
+            vec![
+                format!("Before {}a note{}after.", open, close),
+                format!("{}spanning{}", open, close),
+                format!("{}lines{}Done.", open, close),
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_footnotes() {
+        let source = vec![
+            r#"First{\Scrv_fn \text=a note\end_Scrv_fn} and second.{\Scrv_fn \text=two"#
+                .to_string(),
+            r#"paragraphs\end_Scrv_fn}"#.to_string(),
+            "Next.".to_string(),
+        ];
+
+        let mut adapter = FootnoteAdapter::new(source.clone().into_iter(), false);
+        let lines: Vec<_> = adapter.by_ref().collect();
+        assert_eq!(lines, &["First and second.", "Next."]);
+        assert_eq!(
+            adapter.into_footnotes(),
+            vec![
+                Footnote {
+                    number: 1,
+                    paragraph: 0,
+                    offset: 5,
+                    text: "a note".to_string()
+                },
+                Footnote {
+                    number: 2,
+                    paragraph: 0,
+                    offset: 17,
+                    text: "two\nparagraphs".to_string()
+                }
+            ]
+        );
+
+        let lines: Vec<_> = FootnoteAdapter::new(source.into_iter(), true).collect();
+        assert_eq!(lines, &["First[1] and second.[2]", "Next."]);
+
+        // offsets ignore style tags
+        let source = vec![
+            r#"<$Scr_Ps::0>First<$Scr_Cs::1>{\Scrv_fn \text=a note\end_Scrv_fn}<!$Scr_Cs::1>"#
+                .to_string(),
+        ];
+        let footnotes = FootnoteAdapter::new(source.into_iter(), false).into_footnotes();
+        assert_eq!(footnotes[0].offset, 5);
+    }
 }
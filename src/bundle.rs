@@ -1,33 +1,134 @@
 //! The file system directory structure for a scrivener project
+use crate::error::{Result, ScryError};
+use crate::scrivx::{BinderItem, ProjectDocument, ProjectFormat, ScrivenerProject};
+use crate::storage::{DirectoryStorage, Storage, ZipStorage};
+use lazy_static::lazy_static;
+use regex::Regex;
 use std::convert::AsRef;
 use std::ffi::OsStr;
+use std::fmt;
+use std::io;
 use std::path::{Path, PathBuf};
+use std::rc::Rc;
 use uuid::Uuid;
 
+/// Open a project from its .scrivx file or a zipped backup
+/// containing one, returning the parsed project and its bundle
+pub fn open_project(project_file: &Path) -> Result<(ScrivenerProject, Bundle)> {
+    let (storage, scrivx) = open_storage(project_file)?;
+    let directory = scrivx.parent().ok_or(ScryError::CannotLocateBundle)?;
+    let project = ScrivenerProject::parse(&storage.read(&scrivx)?[..])?;
+    let bundle = Bundle::with_storage(storage.clone(), directory, project.format());
+    Ok((project, bundle))
+}
+
+/// Open a project's .scrivx file for modification, returning the
+/// document and its bundle
+pub fn open_document(project_file: &Path) -> Result<(ProjectDocument, Bundle)> {
+    let (storage, scrivx) = open_storage(project_file)?;
+    let directory = scrivx.parent().ok_or(ScryError::CannotLocateBundle)?;
+    let document = ProjectDocument::parse(&storage.read(&scrivx)?[..])?;
+    let format = document.project()?.format();
+    let bundle = Bundle::with_storage(storage.clone(), directory, format);
+    Ok((document, bundle))
+}
+
+/// Storage for a project and the path of the .scrivx file within it
+fn open_storage(project_file: &Path) -> Result<(Rc<dyn Storage>, PathBuf)> {
+    if project_file.extension() == Some(OsStr::new("zip")) {
+        let zip = ZipStorage::open(project_file)?;
+        let scrivx = zip.find_scrivx().ok_or(ScryError::CannotLocateScrivx)?;
+        Ok((Rc::new(zip), scrivx))
+    } else {
+        Ok((Rc::new(DirectoryStorage), project_file.to_path_buf()))
+    }
+}
+
 /// The file system directory structure for a scrivener project
 ///
-/// This includes all RTF and image content.
+/// This includes all RTF and image content, which may be read from
+/// the file system or some other storage such as a zip archive.
 pub struct Bundle {
+    /// Where the bundle's files are read from
+    storage: Rc<dyn Storage>,
     /// The root directory (containing .scrivx file)
     root: PathBuf,
+    /// The layout of item files within the bundle
+    format: ProjectFormat,
+    /// Write even if the project is open in Scrivener
+    force: bool,
 }
 
 impl Bundle {
-    /// Construct a bundle from base directory
-    pub fn new<T>(root: T) -> Self
+    /// Construct a bundle from base directory and the project's
+    /// file layout
+    pub fn new<T>(root: T, format: ProjectFormat) -> Self
+    where
+        T: AsRef<Path>,
+    {
+        Bundle::with_storage(Rc::new(DirectoryStorage), root, format)
+    }
+
+    /// Construct a bundle with base directory `root` within `storage`
+    pub fn with_storage<T>(storage: Rc<dyn Storage>, root: T, format: ProjectFormat) -> Self
     where
         T: AsRef<Path>,
     {
         Bundle {
+            storage,
             root: root.as_ref().to_owned(),
+            format,
+            force: false,
         }
     }
 
+    /// Allow writes to a project which is locked by Scrivener
+    pub fn set_force(&mut self, force: bool) {
+        self.force = force;
+    }
+
     /// The root directory of the bundle
     pub fn root(&self) -> &Path {
         &self.root
     }
 
+    /// The layout of item files within the bundle
+    pub fn format(&self) -> ProjectFormat {
+        self.format
+    }
+
+    /// Folder containing all item files in a legacy project
+    pub fn docs_folder(&self) -> PathBuf {
+        let mut path = self.root.clone();
+        path.push("Files");
+        path.push("Docs");
+        path
+    }
+
+    /// The lock held by Scrivener if the project is currently open
+    ///
+    /// Scrivener writes a lock file (e.g. `Files/user.lock`) while a
+    /// project is open and removes it on close.
+    pub fn lock(&self) -> Option<ProjectLock> {
+        let mut files = self.root.clone();
+        files.push("Files");
+        self.storage
+            .list(&files)
+            .into_iter()
+            .find(|path| path.extension() == Some(OsStr::new("lock")))
+            .map(|path| {
+                let data = self.storage.read(&path).unwrap_or_default();
+                let mut lock = ProjectLock::parse(path, &data);
+                if lock.since.is_none() {
+                    lock.since = self
+                        .storage
+                        .modified(&lock.path)
+                        .map(|time| time.format("%Y-%m-%d %H:%M:%S %z").to_string());
+                }
+                lock
+            })
+    }
+
     /// Content folder for a binder item
     pub fn binder_item_folder(&self, binder_item: &Uuid) -> PathBuf {
         let mut path = self.root.clone();
@@ -42,30 +143,150 @@ impl Bundle {
     }
 
     /// Retrieve BinderItemFolder for specified content item
-    pub fn binder_item_content(&self, binder_item: &Uuid) -> BinderItemFolder {
-        let folder = self.binder_item_folder(binder_item);
-        BinderItemFolder::new(folder)
+    pub fn binder_item_content(&self, binder_item: &BinderItem) -> BinderItemFolder {
+        let storage = self.storage.clone();
+        match (self.format, &binder_item.id) {
+            (ProjectFormat::Legacy, Some(id)) => {
+                BinderItemFolder::legacy(storage, self.docs_folder(), id)
+            }
+            _ => BinderItemFolder::new(storage, self.binder_item_folder(&binder_item.uuid)),
+        }
+    }
+
+    /// Write a modified project document back to the .scrivx file
+    /// `scrivx` in this bundle
+    ///
+    /// The existing file is first copied to `<name>.scrivx.bak` and is
+    /// then replaced atomically. In current format projects, folders
+    /// are created for any items added to the document.
+    ///
+    /// Fails if the project is open in Scrivener unless forced.
+    pub fn save_document(&self, scrivx: &Path, document: &mut ProjectDocument) -> Result<()> {
+        if !self.force {
+            if let Some(lock) = self.lock() {
+                return Err(ScryError::ProjectLocked(lock));
+            }
+        }
+
+        let data = document.to_bytes()?;
+
+        if self.storage.is_file(scrivx) {
+            let mut backup = scrivx.as_os_str().to_owned();
+            backup.push(".bak");
+            self.storage
+                .write(Path::new(&backup), &self.storage.read(scrivx)?)?;
+        }
+
+        if self.format == ProjectFormat::Current {
+            for uuid in document.created_items() {
+                self.storage.create_dir(&self.binder_item_folder(uuid))?;
+            }
+        }
+
+        self.storage.write(scrivx, &data)?;
+        document.clear_created_items();
+        Ok(())
+    }
+}
+
+/// A lock file indicating the project is open in Scrivener
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProjectLock {
+    /// The lock file
+    pub path: PathBuf,
+    /// User (and machine) holding the lock, if recorded
+    pub holder: Option<String>,
+    /// When the lock was taken, as recorded in the lock file or else
+    /// the time the lock file was last modified
+    pub since: Option<String>,
+}
+
+lazy_static! {
+    static ref PLIST_ENTRY: Regex = Regex::new(
+        r"<key>\s*([^<]*?)\s*</key>\s*<(?:string|date)>\s*([^<]*?)\s*</(?:string|date)>"
+    )
+    .unwrap();
+}
+
+impl ProjectLock {
+    /// Interpret the contents of a lock file
+    ///
+    /// Lock files are either property lists or simple `key: value` /
+    /// `key=value` lines, depending on platform and version. We look
+    /// for the user, computer and date entries, which are left unknown
+    /// if there are none we recognise.
+    pub fn parse(path: PathBuf, data: &[u8]) -> Self {
+        let text = String::from_utf8_lossy(data);
+        let entries: Vec<(String, String)> = if text.contains("<key>") {
+            PLIST_ENTRY
+                .captures_iter(&text)
+                .map(|c| (c[1].to_lowercase(), c[2].to_string()))
+                .collect()
+        } else {
+            text.lines()
+                .filter_map(|line| line.split_once([':', '=']))
+                .map(|(k, v)| (k.trim().to_lowercase(), v.trim().to_string()))
+                .collect()
+        };
+        let entry = |keys: &[&str]| {
+            entries
+                .iter()
+                .find(|(k, v)| keys.contains(&k.as_str()) && !v.is_empty())
+                .map(|(_, v)| v.clone())
+        };
+
+        let user = entry(&["user", "username", "owner", "name"]);
+        let computer = entry(&["computer", "computername", "host", "hostname", "machine"]);
+        let holder = match (user, computer) {
+            (Some(user), Some(computer)) => Some(format!("{} on {}", user, computer)),
+            (user, computer) => user.or(computer),
+        };
+
+        ProjectLock {
+            path,
+            holder,
+            since: entry(&["date", "since", "time", "opened"]),
+        }
+    }
+}
+
+impl fmt::Display for ProjectLock {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} held by {} since {}",
+            self.path.display(),
+            self.holder.as_deref().unwrap_or("unknown user"),
+            self.since.as_deref().unwrap_or("unknown time")
+        )
     }
 }
 
 /// Find the content file under `folder` if it exists
-fn find_content(folder: &Path) -> Option<PathBuf> {
-    for entry in folder.read_dir().ok()?.flatten() {
-        let path = entry.path();
+fn find_content(storage: &dyn Storage, folder: &Path) -> Option<PathBuf> {
+    for path in storage.list(folder) {
         let stem = path.file_stem();
         let ext = path.extension();
         if stem == Some(OsStr::new("content")) && ext != Some(OsStr::new("comments")) {
-            return Some(entry.path());
+            return Some(path);
         }
     }
     None
 }
 
+/// Find the content file for legacy item `id` under `folder` if it exists
+fn find_legacy_content(storage: &dyn Storage, folder: &Path, id: &str) -> Option<PathBuf> {
+    storage
+        .list(folder)
+        .into_iter()
+        .find(|path| path.file_stem() == Some(OsStr::new(id)))
+}
+
 /// Return the specified file under the folder if it exists
-fn existing_child(folder: &Path, name: &str) -> Option<PathBuf> {
+fn existing_child(storage: &dyn Storage, folder: &Path, name: &str) -> Option<PathBuf> {
     let mut path = folder.to_path_buf();
     path.push(name);
-    if path.is_file() {
+    if storage.is_file(&path) {
         Some(path)
     } else {
         None
@@ -73,7 +294,12 @@ fn existing_child(folder: &Path, name: &str) -> Option<PathBuf> {
 }
 
 /// Access to key paths for a binder item
+///
+/// In a legacy project the folder is shared by all items and files
+/// are named by the item's ID.
 pub struct BinderItemFolder {
+    /// Where the item's files are read from
+    storage: Rc<dyn Storage>,
     /// The item's folder
     folder: PathBuf,
     /// The item's content file
@@ -87,13 +313,14 @@ pub struct BinderItemFolder {
 }
 
 impl BinderItemFolder {
-    pub fn new(folder: PathBuf) -> Self {
-        let content = find_content(&folder);
-        let notes = existing_child(&folder, "notes.rtf");
-        let synopsis = existing_child(&folder, "synopsis.txt");
-        let comments = existing_child(&folder, "content.comments");
+    pub fn new(storage: Rc<dyn Storage>, folder: PathBuf) -> Self {
+        let content = find_content(&*storage, &folder);
+        let notes = existing_child(&*storage, &folder, "notes.rtf");
+        let synopsis = existing_child(&*storage, &folder, "synopsis.txt");
+        let comments = existing_child(&*storage, &folder, "content.comments");
 
         BinderItemFolder {
+            storage,
             folder,
             content,
             notes,
@@ -102,6 +329,26 @@ impl BinderItemFolder {
         }
     }
 
+    /// Locate files for item `id` in a legacy project's docs folder
+    /// (`<ID>.rtf`, `<ID>_notes.rtf`, `<ID>_synopsis.txt`).
+    ///
+    /// Legacy projects keep comments inside the content RTF so there
+    /// is no comments file.
+    pub fn legacy(storage: Rc<dyn Storage>, folder: PathBuf, id: &str) -> Self {
+        let content = find_legacy_content(&*storage, &folder, id);
+        let notes = existing_child(&*storage, &folder, &format!("{}_notes.rtf", id));
+        let synopsis = existing_child(&*storage, &folder, &format!("{}_synopsis.txt", id));
+
+        BinderItemFolder {
+            storage,
+            folder,
+            content,
+            notes,
+            synopsis,
+            comments: None,
+        }
+    }
+
     /// Path to item folder
     pub fn folder(&self) -> &Path {
         &self.folder
@@ -126,4 +373,85 @@ impl BinderItemFolder {
     pub fn comments(&self) -> Option<&Path> {
         self.comments.as_deref()
     }
+
+    /// Read one of the item's files
+    pub fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        self.storage.read(path)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    pub fn test_save_document() {
+        let dir = tempfile::Builder::new()
+            .prefix("scry-test-")
+            .suffix(".scriv")
+            .tempdir()
+            .unwrap();
+        let root = dir.path();
+        let scrivx = root.join("test.scrivx");
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder">
+            <Title>Draft</Title>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>
+"#;
+        fs::write(&scrivx, source).unwrap();
+
+        let (mut document, bundle) = open_document(&scrivx).unwrap();
+        let draft = Uuid::parse_str("AAAAAAAA-0000-0000-0000-000000000001").unwrap();
+        let uuid = document.create_text_item(&draft, "New Scene").unwrap();
+        bundle.save_document(&scrivx, &mut document).unwrap();
+        assert!(document.created_items().is_empty());
+
+        assert_eq!(
+            fs::read_to_string(root.join("test.scrivx.bak")).unwrap(),
+            source
+        );
+        assert!(bundle.binder_item_folder(&uuid).is_dir());
+        let (project, _) = open_project(&scrivx).unwrap();
+        assert_eq!(project.item(&uuid).unwrap().title, "New Scene");
+
+        fs::write(
+            root.join("Files").join("user.lock"),
+            "<plist><dict><key>Computer</key><string>desk</string>\
+             <key>Date</key><date>2022-07-01T10:00:00Z</date>\
+             <key>User</key><string>ed</string></dict></plist>",
+        )
+        .unwrap();
+        let lock = bundle.lock().unwrap();
+        assert_eq!(lock.holder.as_deref(), Some("ed on desk"));
+        assert_eq!(lock.since.as_deref(), Some("2022-07-01T10:00:00Z"));
+        assert!(matches!(
+            bundle.save_document(&scrivx, &mut document),
+            Err(ScryError::ProjectLocked(_))
+        ));
+
+        // unrecognised contents fall back on the file's modification time
+        fs::write(root.join("Files").join("user.lock"), "ed@desk").unwrap();
+        let lock = bundle.lock().unwrap();
+        assert_eq!(lock.holder, None);
+        assert!(lock.since.is_some());
+    }
+
+    #[test]
+    pub fn test_parse_lock_lines() {
+        let lock = ProjectLock::parse(
+            PathBuf::from("user.lock"),
+            b"User=ed\nDate: 2022-07-01 10:00:00 +0100\n",
+        );
+        assert_eq!(lock.holder.as_deref(), Some("ed"));
+        assert_eq!(lock.since.as_deref(), Some("2022-07-01 10:00:00 +0100"));
+
+        let lock = ProjectLock::parse(PathBuf::from("user.lock"), b"ed@desk 1656666000\n");
+        assert_eq!(lock.holder, None);
+        assert_eq!(lock.since, None);
+    }
 }
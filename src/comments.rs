@@ -0,0 +1,270 @@
+//! Representation and parsing of out-of-line comments
+//!
+//! Scrivener stores linked comments for an item alongside its content
+//! in a `content.comments` XML file. Each comment carries its own RTF
+//! text, e.g.
+//!
+//! ```xml
+//! <Comments>
+//!     <Comment ID="3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6" Footnote="No" Color="0.999 0.959 0.648">
+//!         <Notes><![CDATA[{\rtf1\ansi ... cut this\}]]></Notes>
+//!     </Comment>
+//! </Comments>
+//! ```
+//!
+//! Linked footnotes are stored the same way but marked
+//! `Footnote="Yes"`.
+use crate::annot;
+use crate::bundle::BinderItemFolder;
+use crate::error::{Result, ScryWarning};
+use crate::rtf;
+use crate::scrivx::{de_from_yes_no, Color};
+use quick_xml::de::from_reader;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::io::{BufReader, Read};
+use uuid::Uuid;
+
+/// The comments attached to a binder item
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct Comments {
+    #[serde(rename = "Comment", default)]
+    pub comments: Vec<Comment>,
+}
+
+impl Comments {
+    /// Parse a comments file
+    pub fn parse<T: Read>(input: T) -> Result<Self> {
+        let r = BufReader::new(input);
+        Ok(from_reader(r)?)
+    }
+
+    /// Iterate over the comments in file order
+    pub fn iter(&self) -> std::slice::Iter<'_, Comment> {
+        self.comments.iter()
+    }
+
+    /// Iterate over the comments that are not linked footnotes
+    pub fn comments(&self) -> impl Iterator<Item = &Comment> {
+        self.iter().filter(|c| !c.footnote)
+    }
+
+    /// Iterate over the linked footnotes
+    pub fn footnotes(&self) -> impl Iterator<Item = &Comment> {
+        self.iter().filter(|c| c.footnote)
+    }
+}
+
+/// Parse a binder item's comments file
+pub fn parse_item_comments(folder: &BinderItemFolder) -> Result<Option<Comments>> {
+    match folder.comments() {
+        Some(path) => Ok(Some(Comments::parse(&folder.read(path)?[..])?)),
+        None => Ok(None),
+    }
+}
+
+/// Parse a binder item's comments file for its linked footnotes
+///
+/// As content doesn't depend on them, a file that can't be parsed is
+/// added to `warnings` and otherwise ignored.
+pub fn parse_item_footnotes(
+    folder: &BinderItemFolder,
+    warnings: &mut Vec<ScryWarning>,
+) -> Option<Comments> {
+    parse_item_comments(folder).unwrap_or_else(|e| {
+        let path = folder.comments().unwrap_or_else(|| folder.folder());
+        warnings.push(ScryWarning::IgnoredFootnotes(path.to_path_buf(), e));
+        None
+    })
+}
+
+/// A single out-of-line comment
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Comment {
+    /// ID referenced by `scrivcmt://` links in the content RTF
+    #[serde(rename = "ID")]
+    pub id: Uuid,
+    #[serde(rename = "Footnote", deserialize_with = "de_from_yes_no", default)]
+    pub footnote: bool,
+    #[serde(rename = "Color", default)]
+    pub color: Option<Color>,
+    #[serde(rename = "Author", default)]
+    pub author: Option<String>,
+    #[serde(rename = "Date", default)]
+    pub date: Option<String>,
+    /// Comment text as RTF
+    #[serde(rename = "Notes", default)]
+    pub notes: String,
+}
+
+impl Comment {
+    /// Paragraphs of comment text
+    pub fn paragraphs(&self) -> Result<Vec<String>> {
+        Ok(rtf::parse_rtf(self.notes.as_bytes())?.collect())
+    }
+}
+
+/// Adapts content paragraphs to carry linked footnotes inline, just
+/// after the text they are anchored to.
+///
+/// Footnotes spliced in this way are then cut out (and numbered in
+/// document order along with any inline footnotes) by
+/// `annot::FootnoteAdapter`.
+pub struct LinkedFootnoteAdapter {
+    /// Content paragraphs
+    source: rtf::ParagraphIterator,
+    /// Text of each linked footnote by ID
+    footnotes: HashMap<Uuid, String>,
+    /// Index of the next paragraph
+    paragraph: usize,
+}
+
+impl LinkedFootnoteAdapter {
+    /// Construct an adapter to splice in the linked footnotes among
+    /// the comments, skipping any whose text can't be parsed and
+    /// adding them to `warnings`
+    pub fn new(
+        source: rtf::ParagraphIterator,
+        comments: Option<&Comments>,
+        warnings: &mut Vec<ScryWarning>,
+    ) -> Self {
+        let mut footnotes = HashMap::new();
+        for footnote in comments.into_iter().flat_map(Comments::footnotes) {
+            match footnote.paragraphs() {
+                Ok(text) => {
+                    footnotes.insert(footnote.id, text.join("\n"));
+                }
+                Err(e) => warnings.push(ScryWarning::SkippedFootnote(footnote.id, e)),
+            }
+        }
+        LinkedFootnoteAdapter {
+            source,
+            footnotes,
+            paragraph: 0,
+        }
+    }
+}
+
+impl Iterator for LinkedFootnoteAdapter {
+    type Item = String;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut line = self.source.next()?;
+        if !self.footnotes.is_empty() {
+            let mut anchors: Vec<_> = self
+                .source
+                .anchors()
+                .into_iter()
+                .filter(|a| a.paragraph == self.paragraph)
+                .filter_map(|a| Some((a.end, self.footnotes.get(&a.comment_id()?)?)))
+                .collect();
+            // splice from the end so earlier offsets stay valid
+            anchors.sort_by_key(|(end, _)| std::cmp::Reverse(*end));
+            for (end, text) in anchors {
+                let idx = line
+                    .char_indices()
+                    .nth(end)
+                    .map_or(line.len(), |(idx, _)| idx);
+                line.insert_str(idx, &annot::inline_footnote(text));
+            }
+        }
+        self.paragraph += 1;
+        Some(line)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_parse_comments() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Comments>
+    <Comment ID="3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6" Footnote="No" Color="1.0 0.5 0.0" Author="Ed" Date="2022-07-01 10:00:00 +0100">
+        <Notes><![CDATA[{\rtf1\ansi\ansicpg1252 cut this\
+}]]></Notes>
+    </Comment>
+    <Comment ID="F7C7BC3A-7E93-4D2B-9A44-5F7E3D2C1B0A" Footnote="Yes">
+        <Notes><![CDATA[{\rtf1\ansi\ansicpg1252 Ibid.}]]></Notes>
+    </Comment>
+</Comments>"#;
+        let comments = Comments::parse(source.as_bytes()).unwrap();
+        assert_eq!(comments.comments.len(), 2);
+
+        let first = &comments.comments[0];
+        assert_eq!(
+            first.id,
+            Uuid::parse_str("3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6").unwrap()
+        );
+        assert!(!first.footnote);
+        assert_eq!(first.color.unwrap().to_string(), "#FF8000");
+        assert_eq!(first.author.as_deref(), Some("Ed"));
+        assert_eq!(first.date.as_deref(), Some("2022-07-01 10:00:00 +0100"));
+        assert_eq!(first.paragraphs().unwrap(), vec!["cut this"]);
+
+        let second = &comments.comments[1];
+        assert!(second.footnote);
+        assert_eq!(second.color, None);
+        assert_eq!(second.author, None);
+        assert_eq!(second.paragraphs().unwrap(), vec!["Ibid."]);
+
+        assert_eq!(comments.comments().count(), 1);
+        assert_eq!(
+            comments.footnotes().map(|c| c.id).collect::<Vec<_>>(),
+            vec![second.id]
+        );
+    }
+
+    #[test]
+    pub fn test_linked_footnotes() {
+        let comments = Comments::parse(
+            r#"<Comments>
+    <Comment ID="3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6" Footnote="No">
+        <Notes><![CDATA[{\rtf1\ansi A comment.}]]></Notes>
+    </Comment>
+    <Comment ID="F7C7BC3A-7E93-4D2B-9A44-5F7E3D2C1B0A" Footnote="Yes">
+        <Notes><![CDATA[{\rtf1\ansi Ibid.}]]></Notes>
+    </Comment>
+</Comments>"#
+                .as_bytes(),
+        )
+        .unwrap();
+        let content = r#"{\rtf1\ansi
+\pard A {\field{\*\fldinst{HYPERLINK "scrivcmt://3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6"}}{\fldrslt comment}} and a {\field{\*\fldinst{HYPERLINK "scrivcmt://F7C7BC3A-7E93-4D2B-9A44-5F7E3D2C1B0A"}}{\fldrslt footnote}} here.\
+}"#;
+        let mut warnings = vec![];
+        let adapter = LinkedFootnoteAdapter::new(
+            rtf::parse_rtf(content.as_bytes()).unwrap(),
+            Some(&comments),
+            &mut warnings,
+        );
+        let mut footnotes = annot::FootnoteAdapter::new(adapter, true);
+        let lines: Vec<_> = footnotes.by_ref().collect();
+        assert_eq!(lines, vec!["A comment and a footnote[1] here."]);
+
+        let footnotes = footnotes.into_footnotes();
+        assert_eq!(footnotes.len(), 1);
+        assert_eq!(footnotes[0].text, "Ibid.");
+        assert_eq!(footnotes[0].offset, 24);
+        assert!(warnings.is_empty());
+    }
+
+    #[test]
+    pub fn test_unparseable_footnotes() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("content.rtf"), "{\\rtf1 Text.}").unwrap();
+        std::fs::write(dir.path().join("content.comments"), "<Comments><Comment").unwrap();
+        let folder = BinderItemFolder::new(
+            std::rc::Rc::new(crate::storage::DirectoryStorage),
+            dir.path().to_path_buf(),
+        );
+
+        let mut warnings = vec![];
+        assert!(parse_item_footnotes(&folder, &mut warnings).is_none());
+        assert!(matches!(
+            warnings.as_slice(),
+            [ScryWarning::IgnoredFootnotes(path, _)] if path.ends_with("content.comments")
+        ));
+    }
+}
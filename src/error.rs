@@ -1,8 +1,11 @@
+use crate::bundle::ProjectLock;
 use json;
 use quick_xml::DeError;
 use rtf_grimoire::tokenizer::ParseError;
 use std::io;
+use std::path::PathBuf;
 use thiserror::Error;
+use uuid::Uuid;
 
 /// Error while processing scrivener project
 #[derive(Error, Debug)]
@@ -13,12 +16,46 @@ pub enum ScryError {
     RtfParse(ParseError),
     #[error("failed to parse XML: {0}")]
     XmlParse(#[from] DeError),
+    #[error("failed to read or write XML: {0}")]
+    Xml(#[from] quick_xml::Error),
     #[error("failed to format JSON: {0}")]
     JsonError(#[from] json::Error),
     #[error("unable to locate bundle containing project")]
     CannotLocateBundle,
     #[error("unable to locate .scrivx project file")]
     CannotLocateScrivx,
+    #[error("no collection named {0:?} in project")]
+    UnknownCollection(String),
+    #[error("no binder items match path {0:?}")]
+    NoPathMatch(String),
+    #[error("no binder item with UUID {0}")]
+    UnknownItem(Uuid),
+    #[error("cannot move binder item {0} into {1}, which is itself or one of its descendants")]
+    InvalidMove(Uuid, Uuid),
+    #[error("no label named {0:?} in project")]
+    UnknownLabel(String),
+    #[error("no status named {0:?} in project")]
+    UnknownStatus(String),
+    #[error("project is open in Scrivener: {0}")]
+    ProjectLocked(ProjectLock),
+    #[error("{0} is not supported in legacy (Scrivener 2) projects")]
+    UnsupportedInLegacy(&'static str),
+    #[error("--standalone requires --format html")]
+    StandaloneRequiresHtml,
+    #[error("invalid pattern: {0}")]
+    InvalidPattern(#[from] regex::Error),
+}
+
+/// A problem worked around rather than abandoning extraction, for
+/// the caller to report
+#[derive(Error, Debug)]
+pub enum ScryWarning {
+    #[error("ignoring linked footnotes in {0}: {1}")]
+    IgnoredFootnotes(PathBuf, ScryError),
+    #[error("skipping footnote {0}: {1}")]
+    SkippedFootnote(Uuid, ScryError),
+    #[error("skipping comment {0}: {1}")]
+    SkippedComment(Uuid, ScryError),
 }
 
 /// Scry result
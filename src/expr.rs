@@ -0,0 +1,566 @@
+//! Filter expressions evaluated against binder items
+//!
+//! A small language for selecting items, e.g.
+//!
+//! ```text
+//! type == Text and status != "Done" and title ~ /^Scene/ and depth <= 3
+//! ```
+//!
+//! Comparisons take the form `FIELD OP VALUE` and may be combined with
+//! `and`, `or`, `not` and parentheses. The fields are `type`, `title`,
+//! `label`, `status`, `keyword`, `depth`, `index`, `uuid`, `path`,
+//! `created`, `modified` and `meta.FIELD` (custom metadata by title or
+//! ID, quoted if necessary, e.g. `meta."Story Date"`).
+//!
+//! Operators are `==`, `!=`, `<`, `<=`, `>`, `>=`, `~` (matches regular
+//! expression) and `!~`. Values may be bare words, numbers, quoted
+//! strings or, for `~` and `!~`, `/regex/` (with an optional `i` flag
+//! for case insensitivity). Depths and dates compare as such; keyword
+//! comparisons succeed if any of the item's keywords satisfy them.
+use crate::scrivx::{parse_time_bound, BinderEntry, MetaDataValue};
+use chrono::{DateTime, FixedOffset};
+use regex::{Regex, RegexBuilder};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Error in a filter expression
+#[derive(Error, Debug)]
+#[error("invalid expression at position {position}: {message}")]
+pub struct ExpressionError {
+    /// Character offset of the problem
+    pub position: usize,
+    pub message: String,
+}
+
+/// A filter expression over binder items
+#[derive(Debug, Clone)]
+pub enum Expression {
+    And(Box<Expression>, Box<Expression>),
+    Or(Box<Expression>, Box<Expression>),
+    Not(Box<Expression>),
+    Compare(Field, Operator, Operand),
+}
+
+/// An item property that expressions can test
+#[derive(Debug, Clone, PartialEq)]
+pub enum Field {
+    Type,
+    Title,
+    Label,
+    Status,
+    Keyword,
+    Depth,
+    Index,
+    Uuid,
+    Path,
+    Created,
+    Modified,
+    /// Custom metadata field by title or ID
+    Meta(String),
+}
+
+/// Comparison operators
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Operator {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Match,
+    NotMatch,
+}
+
+/// The right hand side of a comparison
+#[derive(Debug, Clone)]
+pub enum Operand {
+    Text(String),
+    Pattern(Regex),
+}
+
+/// A field value for an item
+enum Value<'a> {
+    Text(&'a str),
+    Number(usize),
+    Time(DateTime<FixedOffset>),
+    Meta(&'a MetaDataValue),
+}
+
+impl Value<'_> {
+    /// Returns true if the value equals the textual representation
+    fn equals(&self, s: &str) -> bool {
+        match self {
+            Value::Text(t) => *t == s,
+            Value::Number(n) => s.parse() == Ok(*n),
+            Value::Time(t) => parse_time_bound(s) == Ok(*t) || t.date_naive().to_string() == s,
+            Value::Meta(m) => m.matches(s),
+        }
+    }
+
+    /// Order the value relative to the textual representation, if
+    /// they are comparable
+    fn compare(&self, s: &str) -> Option<Ordering> {
+        match self {
+            Value::Text(t) => compare_text(t, s),
+            Value::Meta(MetaDataValue::Text(t) | MetaDataValue::List(t)) => compare_text(t, s),
+            Value::Number(n) => s.parse().ok().map(|o| n.cmp(&o)),
+            Value::Time(t) | Value::Meta(MetaDataValue::Date(t)) => {
+                parse_time_bound(s).ok().map(|o| t.cmp(&o))
+            }
+            Value::Meta(MetaDataValue::Checkbox(_)) => None,
+        }
+    }
+
+    /// Text for pattern matching
+    fn text(&self) -> String {
+        match self {
+            Value::Text(t) => t.to_string(),
+            Value::Number(n) => n.to_string(),
+            Value::Time(t) => t.to_rfc3339(),
+            Value::Meta(m) => m.to_string(),
+        }
+    }
+}
+
+/// Order text numerically if both sides are numbers, otherwise
+/// lexically
+fn compare_text(t: &str, s: &str) -> Option<Ordering> {
+    match (t.parse::<f64>(), s.parse::<f64>()) {
+        (Ok(a), Ok(b)) => a.partial_cmp(&b),
+        _ => Some(t.cmp(s)),
+    }
+}
+
+impl Field {
+    /// Name of a field as written in expressions
+    fn parse(name: &str) -> Option<Field> {
+        Some(match name.to_ascii_lowercase().as_str() {
+            "type" => Field::Type,
+            "title" => Field::Title,
+            "label" => Field::Label,
+            "status" => Field::Status,
+            "keyword" | "keywords" => Field::Keyword,
+            "depth" => Field::Depth,
+            "index" => Field::Index,
+            "uuid" => Field::Uuid,
+            "path" => Field::Path,
+            "created" => Field::Created,
+            "modified" => Field::Modified,
+            _ => return None,
+        })
+    }
+
+    /// Apply `f` to the field's values for an entry (none if the
+    /// item has no value, several for keywords), returning true if
+    /// it is true for any
+    fn any_value<F: Fn(&Value) -> bool>(&self, entry: &BinderEntry, f: F) -> bool {
+        let item = entry.item;
+        match self {
+            Field::Type => f(&Value::Text(&item.r#type.to_string())),
+            Field::Title => f(&Value::Text(&item.title)),
+            Field::Label => item.label.iter().any(|l| f(&Value::Text(&l.name))),
+            Field::Status => item.status.iter().any(|s| f(&Value::Text(&s.name))),
+            Field::Keyword => item.keywords.iter().any(|k| f(&Value::Text(k))),
+            Field::Depth => f(&Value::Number(entry.depth())),
+            Field::Index => f(&Value::Number(entry.index)),
+            Field::Uuid => f(&Value::Text(&item.uuid.to_string().to_ascii_uppercase())),
+            Field::Path => f(&Value::Text(&entry.path().join(" / "))),
+            Field::Created => item.created.iter().any(|t| f(&Value::Time(*t))),
+            Field::Modified => item.modified.iter().any(|t| f(&Value::Time(*t))),
+            Field::Meta(name) => item
+                .custom_metadata
+                .iter()
+                .filter(|m| &m.title == name || &m.id == name)
+                .any(|m| f(&Value::Meta(&m.value))),
+        }
+    }
+}
+
+impl Expression {
+    /// Returns true if the binder entry satisfies the expression
+    pub fn matches(&self, entry: &BinderEntry) -> bool {
+        match self {
+            Expression::And(a, b) => a.matches(entry) && b.matches(entry),
+            Expression::Or(a, b) => a.matches(entry) || b.matches(entry),
+            Expression::Not(e) => !e.matches(entry),
+            Expression::Compare(field, op, operand) => compare(entry, field, *op, operand),
+        }
+    }
+}
+
+/// Evaluate a comparison
+fn compare(entry: &BinderEntry, field: &Field, op: Operator, operand: &Operand) -> bool {
+    match (op, operand) {
+        (Operator::Match, Operand::Pattern(re)) => {
+            field.any_value(entry, |v| re.is_match(&v.text()))
+        }
+        (Operator::NotMatch, Operand::Pattern(re)) => {
+            !field.any_value(entry, |v| re.is_match(&v.text()))
+        }
+        (Operator::Eq, Operand::Text(s)) => field.any_value(entry, |v| v.equals(s)),
+        (Operator::Ne, Operand::Text(s)) => !field.any_value(entry, |v| v.equals(s)),
+        (op, Operand::Text(s)) => field.any_value(entry, |v| {
+            v.compare(s).is_some_and(|ord| match op {
+                Operator::Lt => ord == Ordering::Less,
+                Operator::Le => ord != Ordering::Greater,
+                Operator::Gt => ord == Ordering::Greater,
+                Operator::Ge => ord != Ordering::Less,
+                _ => false,
+            })
+        }),
+        // the parser only produces patterns for match operators
+        _ => false,
+    }
+}
+
+/// Lexical tokens
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    /// Bare word or number
+    Word(String),
+    /// Quoted string
+    Quoted(String),
+    /// `/regex/flags`
+    Regex(String, String),
+    Op(Operator),
+    Open,
+    Close,
+}
+
+/// Split an expression into tokens with their positions
+fn tokenise(s: &str) -> Result<Vec<(usize, Token)>, ExpressionError> {
+    let chars: Vec<char> = s.chars().collect();
+    let error = |position, message: &str| ExpressionError {
+        position,
+        message: message.to_string(),
+    };
+    let mut tokens = vec![];
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '=' if next == Some('=') => Token::Op(Operator::Eq),
+            '!' if next == Some('=') => Token::Op(Operator::Ne),
+            '!' if next == Some('~') => Token::Op(Operator::NotMatch),
+            '<' if next == Some('=') => Token::Op(Operator::Le),
+            '>' if next == Some('=') => Token::Op(Operator::Ge),
+            '<' => Token::Op(Operator::Lt),
+            '>' => Token::Op(Operator::Gt),
+            '~' => Token::Op(Operator::Match),
+            '"' | '\'' | '/' => {
+                let mut text = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(error(start, "unterminated string or pattern")),
+                        Some(&d) if d == c => break,
+                        Some('\\') if c != '/' || chars.get(i + 1) == Some(&'/') => {
+                            i += 1;
+                            text.extend(chars.get(i));
+                        }
+                        Some(&d) => text.push(d),
+                    }
+                    i += 1;
+                }
+                if c == '/' {
+                    let mut flags = String::new();
+                    while let Some(&f) = chars.get(i + 1).filter(|f| f.is_alphabetic()) {
+                        flags.push(f);
+                        i += 1;
+                    }
+                    Token::Regex(text, flags)
+                } else {
+                    Token::Quoted(text)
+                }
+            }
+            c if c.is_alphanumeric() || c == '_' || c == '-' || c == ':' => {
+                while chars
+                    .get(i + 1)
+                    .is_some_and(|&c| c.is_alphanumeric() || "_-:+.".contains(c))
+                {
+                    i += 1;
+                }
+                Token::Word(chars[start..=i].iter().collect())
+            }
+            _ => return Err(error(start, &format!("unexpected character {:?}", c))),
+        };
+        if let Token::Op(op) = token {
+            if matches!(
+                op,
+                Operator::Eq | Operator::Ne | Operator::NotMatch | Operator::Le | Operator::Ge
+            ) {
+                i += 1;
+            }
+        }
+        tokens.push((start, token));
+        i += 1;
+    }
+
+    Ok(tokens)
+}
+
+/// Recursive descent parser over tokens
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    /// Length of the source, for errors at the end
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(_, t)| t)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).map(|(_, t)| t.clone());
+        self.pos += 1;
+        token
+    }
+
+    fn error(&self, message: &str) -> ExpressionError {
+        ExpressionError {
+            position: self
+                .tokens
+                .get(self.pos)
+                .map(|(p, _)| *p)
+                .unwrap_or(self.end),
+            message: message.to_string(),
+        }
+    }
+
+    /// Returns true (and consumes it) if the next token is the
+    /// specified keyword
+    fn keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn or(&mut self) -> Result<Expression, ExpressionError> {
+        let mut expr = self.and()?;
+        while self.keyword("or") {
+            expr = Expression::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expression, ExpressionError> {
+        let mut expr = self.unary()?;
+        while self.keyword("and") {
+            expr = Expression::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expression, ExpressionError> {
+        if self.keyword("not") {
+            return Ok(Expression::Not(Box::new(self.unary()?)));
+        }
+        if self.peek() == Some(&Token::Open) {
+            self.pos += 1;
+            let expr = self.or()?;
+            return match self.next() {
+                Some(Token::Close) => Ok(expr),
+                _ => {
+                    self.pos -= 1;
+                    Err(self.error("expected ')'"))
+                }
+            };
+        }
+        self.comparison()
+    }
+
+    fn field(&mut self) -> Result<Field, ExpressionError> {
+        let word = match self.peek() {
+            Some(Token::Word(w)) => w.clone(),
+            _ => return Err(self.error("expected field name")),
+        };
+        let field = match word.split_once('.') {
+            Some((meta, "")) if meta.eq_ignore_ascii_case("meta") => {
+                self.pos += 1;
+                match self.peek() {
+                    Some(Token::Quoted(name)) | Some(Token::Word(name)) => {
+                        Field::Meta(name.clone())
+                    }
+                    _ => return Err(self.error("expected custom metadata field name")),
+                }
+            }
+            Some((meta, name)) if meta.eq_ignore_ascii_case("meta") => {
+                Field::Meta(name.to_string())
+            }
+            _ => Field::parse(&word)
+                .ok_or_else(|| self.error(&format!("unknown field {:?}", word)))?,
+        };
+        self.pos += 1;
+        Ok(field)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, ExpressionError> {
+        let field = self.field()?;
+        let op = match self.next() {
+            Some(Token::Op(op)) => op,
+            _ => {
+                self.pos -= 1;
+                return Err(self.error("expected comparison operator"));
+            }
+        };
+        let is_match = matches!(op, Operator::Match | Operator::NotMatch);
+        let operand = match self.next() {
+            Some(Token::Word(s)) | Some(Token::Quoted(s)) if !is_match => Operand::Text(s),
+            Some(Token::Word(s)) | Some(Token::Quoted(s)) => Operand::Pattern(self.regex(&s, "")?),
+            Some(Token::Regex(s, flags)) if is_match => Operand::Pattern(self.regex(&s, &flags)?),
+            _ => {
+                self.pos -= 1;
+                return Err(self.error("expected value"));
+            }
+        };
+        Ok(Expression::Compare(field, op, operand))
+    }
+
+    /// Compile a regular expression from the previous token
+    fn regex(&self, pattern: &str, flags: &str) -> Result<Regex, ExpressionError> {
+        RegexBuilder::new(pattern)
+            .case_insensitive(flags.contains('i'))
+            .build()
+            .map_err(|e| ExpressionError {
+                position: self.tokens[self.pos - 1].0,
+                message: e.to_string(),
+            })
+    }
+}
+
+impl FromStr for Expression {
+    type Err = ExpressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parser = Parser {
+            tokens: tokenise(s)?,
+            pos: 0,
+            end: s.chars().count(),
+        };
+        let expr = parser.or()?;
+        if parser.pos < parser.tokens.len() {
+            return Err(parser.error("unexpected input after expression"));
+        }
+        Ok(expr)
+    }
+}
+
+impl fmt::Display for Operator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            Operator::Eq => "==",
+            Operator::Ne => "!=",
+            Operator::Lt => "<",
+            Operator::Le => "<=",
+            Operator::Gt => ">",
+            Operator::Ge => ">=",
+            Operator::Match => "~",
+            Operator::NotMatch => "!~",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::scrivx::ScrivenerProject;
+
+    const PROJECT: &str = r#"<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder">
+            <Title>Draft</Title>
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002" Type="Text" Modified="2022-07-02 10:00:00 +0100">
+                    <Title>Scene One</Title>
+                    <MetaData>
+                        <StatusID>1</StatusID>
+                        <CustomMetaData>
+                            <MetaDataItem><FieldID>pov</FieldID><Value>Anna</Value></MetaDataItem>
+                        </CustomMetaData>
+                    </MetaData>
+                    <Keywords><KeywordID>0</KeywordID><KeywordID>1</KeywordID></Keywords>
+                </BinderItem>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003" Type="Text" Modified="2022-06-01 10:00:00 +0100">
+                    <Title>Interlude</Title>
+                    <MetaData><StatusID>0</StatusID></MetaData>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+    <StatusSettings>
+        <StatusItems>
+            <Status ID="0">Done</Status>
+            <Status ID="1">First Draft</Status>
+        </StatusItems>
+    </StatusSettings>
+    <Keywords>
+        <Keyword ID="0"><Title>Anna</Title></Keyword>
+        <Keyword ID="1"><Title>Paris</Title></Keyword>
+    </Keywords>
+    <CustomMetaDataSettings>
+        <MetaDataField Type="Text" ID="pov"><Title>POV Character</Title></MetaDataField>
+    </CustomMetaDataSettings>
+</ScrivenerProject>"#;
+
+    /// Titles of the items matching an expression
+    fn select(expression: &str) -> Vec<String> {
+        let project = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        let expression: Expression = expression.parse().unwrap();
+        project
+            .entries()
+            .filter(|e| expression.matches(e))
+            .map(|e| e.item.title.clone())
+            .collect()
+    }
+
+    #[test]
+    pub fn test_evaluate_expressions() {
+        assert_eq!(
+            select(r#"type == Text and status != "Done" and title ~ /^Scene/ and depth <= 3"#),
+            vec!["Scene One"]
+        );
+        assert_eq!(
+            select("depth == 0 or title ~ /interLUDE/i"),
+            vec!["Draft", "Interlude"]
+        );
+        assert_eq!(select("not (type == Text)"), vec!["Draft"]);
+        assert_eq!(select("keyword == Paris"), vec!["Scene One"]);
+        assert_eq!(select("keyword != Paris and depth > 0"), vec!["Interlude"]);
+        assert_eq!(select(r#"meta."POV Character" == Anna"#), vec!["Scene One"]);
+        assert_eq!(select("meta.pov !~ An"), vec!["Draft", "Interlude"]);
+        assert_eq!(select("modified < 2022-07-01"), vec!["Interlude"]);
+        assert_eq!(select("path == 'Draft / Interlude'"), vec!["Interlude"]);
+        assert_eq!(select("index >= 1"), vec!["Interlude"]);
+    }
+
+    #[test]
+    pub fn test_expression_errors() {
+        let error = |s: &str| s.parse::<Expression>().unwrap_err();
+        assert_eq!(error("colour == red").position, 0);
+        assert_eq!(error("title == ").position, 9);
+        assert_eq!(error("title == /x/").position, 9);
+        assert_eq!(error("(title == x").position, 11);
+        assert_eq!(error("title ~ /(/").position, 8);
+        assert_eq!(error("title == x y").position, 11);
+        assert_eq!(error("title == \"x").position, 9);
+    }
+}
@@ -8,15 +8,26 @@
 use crate::annot;
 use crate::bundle::BinderItemFolder;
 use crate::bundle::Bundle;
-use crate::error::ScryError;
+use crate::comments;
+use crate::error::{ScryError, ScryWarning};
+use crate::expr::Expression;
+use crate::glob::BinderPattern;
+use crate::markup::{self, Markup};
 use crate::rtf;
-use crate::scrivx::{BinderItem, BinderItemType, BinderIterator, ScrivenerProject};
+use crate::scrivx::{
+    BinderEntry, BinderItem, BinderItemType, BinderPathIterator, MetaDataValue, ProjectFormat,
+    ScrivenerProject,
+};
+use crate::search;
 use crate::tag;
+use chrono::{DateTime, FixedOffset};
 use std::{
-    collections::HashSet,
+    cell::RefCell,
+    collections::{HashMap, HashSet, VecDeque},
     ffi::OsStr,
-    fs::File,
-    io::{self, stdout, BufRead, Read},
+    io::{self, stdout, BufRead},
+    path::Path,
+    rc::Rc,
 };
 use uuid::Uuid;
 
@@ -35,6 +46,8 @@ pub enum FolderSpec {
     NamedFolder(String),
     /// Any top-level folder except trash (will match conflicts)
     Any,
+    /// Items at any depth whose binder path matches a glob
+    Path(BinderPattern),
 }
 
 /// Returns true if item matches the folder spec
@@ -45,22 +58,278 @@ fn matches(item: &BinderItem, folder_spec: &FolderSpec) -> bool {
         FolderSpec::TrashFolder => item.r#type == BinderItemType::TrashFolder,
         FolderSpec::NamedFolder(ref s) => &item.title == s,
         FolderSpec::Any => item.r#type != BinderItemType::TrashFolder,
+        FolderSpec::Path(_) => false,
     }
 }
 
 /// Create a binder iterator from a project and set of folder specifications
+///
+/// Top-level folder specs select whole folders. Path specs select
+/// the items, at any depth, whose paths match, and it is an error if
+/// a path spec matches nothing.
 pub fn binder_iterator(
     project: &ScrivenerProject,
     folder_specs: HashSet<FolderSpec>,
-) -> BinderIterator {
+) -> Result<ItemIterator<'_>, ScryError> {
     let roots: Vec<_> = project
         .binder
         .binder_items
         .iter()
-        .filter(|it| folder_specs.iter().any(|spec| matches(it, spec)))
+        .enumerate()
+        .filter(|(_, it)| folder_specs.iter().any(|spec| matches(it, spec)))
+        .map(|(index, it)| BinderEntry::root(it, index))
+        .collect();
+
+    let patterns: Vec<BinderPattern> = folder_specs
+        .into_iter()
+        .filter_map(|spec| match spec {
+            FolderSpec::Path(pattern) => Some(pattern),
+            _ => None,
+        })
         .collect();
 
-    BinderIterator::new(roots)
+    if patterns.is_empty() {
+        return Ok(Box::new(BinderPathIterator::new(roots)));
+    }
+
+    for pattern in &patterns {
+        if !project.entries().any(|e| pattern_selects(pattern, &e)) {
+            return Err(ScryError::NoPathMatch(pattern.to_string()));
+        }
+    }
+
+    // top-level folders by index as legacy items have no UUIDs
+    let roots: HashSet<usize> = roots.iter().map(|e| e.index).collect();
+    Ok(Box::new(project.entries().filter(move |e| {
+        roots.contains(&e.position()[0]) || patterns.iter().any(|p| pattern_selects(p, e))
+    })))
+}
+
+/// Returns true if the pattern matches the entry's path, leaving out
+/// the trash unless the pattern names it explicitly
+fn pattern_selects(pattern: &BinderPattern, entry: &BinderEntry) -> bool {
+    let top = entry.ancestors.first().copied().unwrap_or(entry.item);
+    (top.r#type != BinderItemType::TrashFolder || pattern.names_top_level(&top.title))
+        && pattern.matches(&entry.path())
+}
+
+/// Specifies criteria that each selected item must satisfy
+///
+/// Items that fail the criteria are skipped but their descendents are
+/// still considered. Where several values are given for one
+/// criterion, an item need only match one of them.
+#[derive(Clone, Default)]
+pub struct ItemFilter {
+    /// Label names to accept
+    pub labels: Vec<String>,
+    /// Status names to accept
+    pub statuses: Vec<String>,
+    /// Keyword names to accept (including any child keywords)
+    pub keywords: Vec<String>,
+    /// Custom metadata (field title or ID, value) pairs to accept
+    pub metadata: Vec<(String, String)>,
+    /// Accept only items modified at or after this time
+    pub modified_since: Option<DateTime<FixedOffset>>,
+    /// Accept only items modified before this time
+    pub modified_before: Option<DateTime<FixedOffset>>,
+    /// Expressions that must all hold
+    pub conditions: Vec<Expression>,
+}
+
+impl ItemFilter {
+    /// IDs of the accepted keywords and their descendents in the
+    /// project, to be resolved once and passed to `accepts`
+    pub fn keyword_ids(&self, project: &ScrivenerProject) -> HashSet<i32> {
+        project
+            .keywords()
+            .iter()
+            .flat_map(|k| k.iter())
+            .filter(|k| self.keywords.contains(&k.title))
+            .flat_map(|k| k.iter())
+            .map(|k| k.id)
+            .collect()
+    }
+
+    /// Returns true if the item satisfies all criteria, given the
+    /// resolved `keyword_ids`
+    pub fn accepts(&self, keyword_ids: &HashSet<i32>, entry: &BinderEntry) -> bool {
+        let item = entry.item;
+        one_of(&self.labels, item.label.as_ref().map(|l| &l.name))
+            && one_of(&self.statuses, item.status.as_ref().map(|s| &s.name))
+            && self.accepts_keywords(keyword_ids, item)
+            && self.accepts_metadata(item)
+            && self.accepts_modified(item)
+            && self.conditions.iter().all(|c| c.matches(entry))
+    }
+
+    /// Returns true if the item's modification time is within bounds
+    fn accepts_modified(&self, item: &BinderItem) -> bool {
+        if self.modified_since.is_none() && self.modified_before.is_none() {
+            return true;
+        }
+
+        item.modified.is_some_and(|modified| {
+            self.modified_since.is_none_or(|since| modified >= since)
+                && self.modified_before.is_none_or(|before| modified < before)
+        })
+    }
+
+    /// Returns true if, for each field constrained, the item's value
+    /// matches one of those accepted
+    fn accepts_metadata(&self, item: &BinderItem) -> bool {
+        self.metadata.iter().all(|(field, _)| {
+            let value = item
+                .custom_metadata
+                .iter()
+                .find(|v| &v.title == field || &v.id == field);
+            self.metadata
+                .iter()
+                .filter(|(f, _)| f == field)
+                .any(|(_, accepted)| value.is_some_and(|v| v.value.matches(accepted)))
+        })
+    }
+
+    /// Returns true if the item carries one of the keywords or one of
+    /// their descendents
+    fn accepts_keywords(&self, keyword_ids: &HashSet<i32>, item: &BinderItem) -> bool {
+        if self.keywords.is_empty() {
+            return true;
+        }
+
+        item.keyword_refs
+            .ids
+            .iter()
+            .any(|id| keyword_ids.contains(id))
+    }
+}
+
+/// True if no values are accepted (i.e. no constraint) or the value
+/// is one of those accepted
+fn one_of(accepted: &[String], value: Option<&String>) -> bool {
+    accepted.is_empty() || value.is_some_and(|v| accepted.contains(v))
+}
+
+/// An iterator over selected binder items and their positions
+pub type ItemIterator<'a> = Box<dyn Iterator<Item = BinderEntry<'a>> + 'a>;
+
+/// Assigns outline numbers (e.g. 2.3.1) to selected entries relative
+/// to the selected root above them.
+///
+/// The root of an entry is its outermost selected ancestor, or the
+/// entry itself if no ancestor was selected, and is not numbered.
+/// So selecting the draft folder numbers its top-level items 1, 2,
+/// ... and selecting `Draft/Part Two/**` numbers the items directly
+/// under Part Two 1, 2, .... Numbers follow positions in the binder so
+/// filtering items out does not renumber the rest.
+///
+/// Entries must be numbered in binder order.
+#[derive(Default)]
+pub struct OutlineNumbering {
+    /// Binder positions of the entries numbered so far
+    selected: HashSet<Vec<usize>>,
+}
+
+impl OutlineNumbering {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record the entry as selected and return its outline number
+    /// (empty for a root)
+    pub fn number(&mut self, entry: &BinderEntry) -> String {
+        let position = entry.position();
+        let root = (0..entry.depth())
+            .find(|depth| self.selected.contains(&position[..=*depth]))
+            .unwrap_or_else(|| entry.depth());
+        self.selected.insert(position);
+        entry
+            .outline_number(root)
+            .iter()
+            .map(|n| n.to_string())
+            .collect::<Vec<_>>()
+            .join(".")
+    }
+}
+
+/// Specifies which binder items to extract
+#[derive(Clone, Default)]
+pub struct Selection {
+    /// Top-level folders to include
+    pub folder_specs: HashSet<FolderSpec>,
+    /// Title of a collection to take items from (in collection
+    /// order) instead of the folders
+    pub collection: Option<String>,
+    /// Specific items to take (in the order given) instead of the
+    /// folders
+    pub items: Vec<Uuid>,
+    /// Also take the descendants of specific items
+    pub descendants: bool,
+    /// Skip items (and their subtrees) excluded from compile
+    pub compile_only: bool,
+    /// Criteria for items to include
+    pub item_filter: ItemFilter,
+}
+
+/// Create an iterator over the selected items
+pub fn select_items<'a>(
+    project: &'a ScrivenerProject,
+    bundle: &Bundle,
+    selection: &Selection,
+) -> Result<ItemIterator<'a>, ScryError> {
+    let items: ItemIterator<'a> = if let Some(ref title) = selection.collection {
+        let collection = project
+            .collection(title)
+            .ok_or_else(|| ScryError::UnknownCollection(title.clone()))?;
+        let items = search::collection_items(project, bundle, collection)?;
+        // keyed by identity as legacy items have no UUIDs; an item
+        // listed more than once is output each time
+        let entries: HashMap<*const BinderItem, BinderEntry<'a>> = project
+            .entries()
+            .map(|e| (e.item as *const BinderItem, e))
+            .collect();
+        Box::new(
+            items
+                .into_iter()
+                .filter_map(move |item| entries.get(&(item as *const BinderItem)).cloned()),
+        )
+    } else if !selection.items.is_empty() {
+        // legacy items all share the nil UUID
+        if project.format() == ProjectFormat::Legacy {
+            return Err(ScryError::UnsupportedInLegacy("selecting items by UUID"));
+        }
+        let entries: HashMap<Uuid, BinderEntry<'a>> =
+            project.entries().map(|e| (e.item.uuid, e)).collect();
+        let roots = selection
+            .items
+            .iter()
+            .map(|uuid| {
+                entries
+                    .get(uuid)
+                    .cloned()
+                    .ok_or(ScryError::UnknownItem(*uuid))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let mut seen = HashSet::new();
+        if selection.descendants {
+            Box::new(BinderPathIterator::new(roots).filter(move |e| seen.insert(e.item.uuid)))
+        } else {
+            Box::new(roots.into_iter().filter(move |e| seen.insert(e.item.uuid)))
+        }
+    } else {
+        binder_iterator(project, selection.folder_specs.clone())?
+    };
+
+    let item_filter = selection.item_filter.clone();
+    let keyword_ids = item_filter.keyword_ids(project);
+    let compile_only = selection.compile_only;
+    Ok(Box::new(items.filter(move |entry| {
+        (!compile_only || entry.included_in_compile()) && item_filter.accepts(&keyword_ids, entry)
+    })))
+}
+
+/// Read and parse one of an item's RTF files
+fn read_rtf(folder: &BinderItemFolder, path: &Path) -> Result<rtf::ParagraphIterator, ScryError> {
+    rtf::parse_rtf(&folder.read(path)?)
 }
 
 /// Specifies content type to extract for each item
@@ -78,6 +347,31 @@ pub enum ContentSpec {
     Inlines,
     /// Out of line comments from item
     Comments,
+    /// Inline footnotes from item RTF content
+    Footnotes,
+}
+
+/// Syntax of extracted text
+#[derive(PartialEq, Eq, Clone, Copy, Debug, Default)]
+pub enum OutputFormat {
+    /// Plain text with formatting discarded
+    #[default]
+    Text,
+    /// Markdown with inline formatting and headings
+    Markdown,
+    /// HTML with inline formatting, headings and links
+    Html,
+}
+
+impl OutputFormat {
+    /// The markup to render paragraphs with, if any
+    pub fn markup(self) -> Option<Box<dyn Markup>> {
+        match self {
+            OutputFormat::Text => None,
+            OutputFormat::Markdown => Some(Box::new(markup::Markdown)),
+            OutputFormat::Html => Some(Box::new(markup::Html)),
+        }
+    }
 }
 
 /// Iterator over selected content in a Scrivener binder item
@@ -90,10 +384,24 @@ pub struct ContentIterator {
     folder: BinderItemFolder,
     /// Content specs remaining to satisfy
     content_specs: HashSet<ContentSpec>,
+    /// Leave numbered markers in content in place of footnotes
+    footnote_markers: bool,
+    /// Syntax of output
+    format: OutputFormat,
+    /// Heading level of the title in marked up output
+    title_level: usize,
     /// Current iterator
     iterator: Option<Box<dyn Iterator<Item = String>>>,
+    /// Content paragraphs, shared with the content iterator so the
+    /// footnotes cut from them can be read off afterwards
+    content: Option<Rc<RefCell<ContentParagraphs>>>,
+    /// Problems worked around so far
+    warnings: Vec<ScryWarning>,
 }
 
+/// Content paragraphs with footnotes cut out
+type ContentParagraphs = annot::FootnoteAdapter<Box<dyn Iterator<Item = String>>>;
+
 impl ContentIterator {
     /// Construct a new content iterator for an item
     pub fn new(
@@ -107,27 +415,99 @@ impl ContentIterator {
             title,
             folder: folder_content,
             content_specs: content_specs.clone(),
+            footnote_markers: false,
+            format: OutputFormat::Text,
+            title_level: 1,
             iterator: None,
+            content: None,
+            warnings: vec![],
+        }
+    }
+
+    /// Render output in the specified syntax, marking up the title
+    /// as a heading of the given level
+    pub fn set_format(&mut self, format: OutputFormat, title_level: usize) {
+        self.format = format;
+        self.title_level = title_level;
+    }
+
+    /// Read and parse one of the item's RTF files, retaining
+    /// formatting if it is to be rendered
+    fn read_rtf(&self, path: &Path) -> Result<rtf::ParagraphIterator, ScryError> {
+        let data = self.folder.read(path)?;
+        match self.format {
+            OutputFormat::Text => rtf::parse_rtf(&data),
+            _ => rtf::parse_rtf_marked(&data),
         }
     }
 
+    /// Box an iterator over paragraphs, rendering them if output is
+    /// marked up
+    fn render<I>(&self, it: I) -> Box<dyn Iterator<Item = String>>
+    where
+        I: Iterator<Item = String> + 'static,
+    {
+        match self.format.markup() {
+            Some(markup) => Box::new(it.map(move |p| markup.paragraph(p))),
+            None => Box::new(it),
+        }
+    }
+
+    /// Box an iterator over paragraphs, rendering them if output is
+    /// marked up or otherwise stripping Scrivener's style tags
+    fn render_tagged<I>(&self, it: I) -> Box<dyn Iterator<Item = String>>
+    where
+        I: Iterator<Item = String> + 'static,
+    {
+        match self.format {
+            OutputFormat::Text => Box::new(it.map(tag::strip_tags)),
+            _ => self.render(it),
+        }
+    }
+
+    /// Leave numbered markers (e.g. "[1]") in content in place of
+    /// inline footnotes, and number the footnotes to match
+    pub fn set_footnote_markers(&mut self, footnote_markers: bool) {
+        self.footnote_markers = footnote_markers;
+    }
+
+    /// Take the problems worked around so far
+    pub fn take_warnings(&mut self) -> Vec<ScryWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
     /// Create iterator over synopsis lines
-    fn synopsis_line_iterator(&self) -> Option<io::Lines<io::BufReader<File>>> {
+    fn synopsis_line_iterator(&self) -> Option<io::Lines<io::Cursor<Vec<u8>>>> {
         if let Some(path) = self.folder.synopsis() {
-            let file = File::open(path).ok()?;
-            Some(io::BufReader::new(file).lines())
+            let data = self.folder.read(path).ok()?;
+            Some(io::Cursor::new(data).lines())
         } else {
             None
         }
     }
 
-    /// Create iterator over content paragraphs
-    fn content_paragraph_iterator(
-        &self,
-    ) -> Option<annot::AnnotationAdapter<rtf::ParagraphIterator>> {
+    /// Create iterator over content paragraphs, either skipping inline
+    /// annotations or marking them to be rendered in place
+    fn content_paragraph_iterator(&mut self, mark_annotations: bool) -> Option<ContentParagraphs> {
         if let Some(path) = self.folder.content() {
             if path.extension() == Some(OsStr::new("rtf")) {
-                rtf::parse_rtf_file(path).ok().map(annot::skip_annotations)
+                let source = self.read_rtf(path).ok()?;
+                let item_comments =
+                    comments::parse_item_footnotes(&self.folder, &mut self.warnings);
+                let paragraphs = comments::LinkedFootnoteAdapter::new(
+                    source,
+                    item_comments.as_ref(),
+                    &mut self.warnings,
+                );
+                let paragraphs: Box<dyn Iterator<Item = String>> = if mark_annotations {
+                    Box::new(annot::mark_annotations(paragraphs))
+                } else {
+                    Box::new(annot::skip_annotations(paragraphs))
+                };
+                Some(annot::FootnoteAdapter::new(
+                    paragraphs,
+                    self.footnote_markers,
+                ))
             } else {
                 None
             }
@@ -142,7 +522,7 @@ impl ContentIterator {
     ) -> Option<annot::AnnotationAdapter<rtf::ParagraphIterator>> {
         if let Some(path) = self.folder.content() {
             if path.extension() == Some(OsStr::new("rtf")) {
-                rtf::parse_rtf_file(path).ok().map(annot::only_annotations)
+                self.read_rtf(path).ok().map(annot::only_annotations)
             } else {
                 None
             }
@@ -151,11 +531,31 @@ impl ContentIterator {
         }
     }
 
+    /// Create iterator over inline footnotes, reusing the content
+    /// paragraphs if they have already been read
+    fn content_footnote_iterator(&mut self) -> Option<std::vec::IntoIter<String>> {
+        let footnotes = match self.content.take() {
+            Some(content) => content.borrow_mut().take_footnotes(),
+            None => self.content_paragraph_iterator(false)?.into_footnotes(),
+        };
+        let texts: Vec<String> = footnotes
+            .into_iter()
+            .map(|footnote| {
+                if self.footnote_markers {
+                    format!("{} {}", footnote.marker(), footnote.text)
+                } else {
+                    footnote.text
+                }
+            })
+            .collect();
+        Some(texts.into_iter())
+    }
+
     /// Create iterator over notes paragraphs
     fn notes_paragraph_iterator(&self) -> Option<rtf::ParagraphIterator> {
         if let Some(path) = self.folder.notes() {
             if path.extension() == Some(OsStr::new("rtf")) {
-                rtf::parse_rtf_file(path).ok()
+                self.read_rtf(path).ok()
             } else {
                 None
             }
@@ -164,43 +564,89 @@ impl ContentIterator {
         }
     }
 
+    /// Create iterator over paragraphs of out-of-line comments
+    fn comment_paragraph_iterator(&mut self) -> Option<std::vec::IntoIter<String>> {
+        let comments = comments::parse_item_comments(&self.folder).ok()??;
+        let mut paragraphs = vec![];
+        for comment in comments.comments() {
+            match comment.paragraphs() {
+                Ok(text) => paragraphs.extend(text),
+                Err(e) => self
+                    .warnings
+                    .push(ScryWarning::SkippedComment(comment.id, e)),
+            }
+        }
+        Some(paragraphs.into_iter())
+    }
+
     /// Load up the next iterator based on the remaining content specs
     fn load_iterator(&mut self) -> bool {
         if self.content_specs.remove(&ContentSpec::Title) {
-            self.iterator = Some(Box::new(std::iter::once(self.title.clone())));
+            let title = match self.format.markup() {
+                Some(markup) => markup.heading(self.title_level, &self.title),
+                None => self.title.clone(),
+            };
+            self.iterator = Some(Box::new(std::iter::once(title)));
             return true;
         }
 
         if self.content_specs.remove(&ContentSpec::Synopsis) {
             if let Some(it) = self.synopsis_line_iterator() {
-                self.iterator = Some(Box::new(it.map(|s| s.unwrap()))); // TODO: it of result
+                self.iterator = Some(self.render(it.map(|s| s.unwrap()))); // TODO: it of result
                 return true;
             }
         }
 
         if self.content_specs.remove(&ContentSpec::Content) {
-            if let Some(it) = self.content_paragraph_iterator() {
-                self.iterator = Some(Box::new(it.map(tag::strip_tags)));
+            // marked up output renders annotations in place
+            let mark_annotations = self.format != OutputFormat::Text
+                && self.content_specs.remove(&ContentSpec::Inlines);
+            if let Some(it) = self.content_paragraph_iterator(mark_annotations) {
+                let content = Rc::new(RefCell::new(it));
+                self.content = Some(content.clone());
+                let it = std::iter::from_fn(move || content.borrow_mut().next());
+                self.iterator = Some(self.render_tagged(it));
+                return true;
+            }
+        }
+
+        if self.content_specs.remove(&ContentSpec::Footnotes) {
+            if let Some(it) = self.content_footnote_iterator() {
+                self.iterator = Some(self.render_tagged(it));
                 return true;
             }
         }
 
         if self.content_specs.remove(&ContentSpec::Notes) {
             if let Some(it) = self.notes_paragraph_iterator() {
-                self.iterator = Some(Box::new(it));
+                self.iterator = Some(self.render(it));
                 return true;
             }
         }
 
         if self.content_specs.remove(&ContentSpec::Inlines) {
             if let Some(it) = self.content_annotation_iterator() {
-                self.iterator = Some(Box::new(it));
+                self.iterator = Some(match self.format {
+                    OutputFormat::Text => Box::new(it),
+                    // render as asides
+                    _ => self.render(it.map(|a| {
+                        format!(
+                            "{}{}{}",
+                            markup::ANNOTATION_OPEN,
+                            a,
+                            markup::ANNOTATION_CLOSE
+                        )
+                    })),
+                });
                 return true;
             }
         }
 
         if self.content_specs.remove(&ContentSpec::Comments) {
-            // TODO: comment iterator
+            if let Some(it) = self.comment_paragraph_iterator() {
+                self.iterator = Some(self.render(it));
+                return true;
+            }
         }
 
         false
@@ -239,10 +685,20 @@ pub struct Extractor {
     project: ScrivenerProject,
     /// The bundle folder containing content
     bundle: Bundle,
-    /// Top-level folders to include
-    folder_specs: HashSet<FolderSpec>,
+    /// Items to include
+    selection: Selection,
     /// Content type to include
     content_specs: HashSet<ContentSpec>,
+    /// Prefix each string with the binder path of its item
+    prefix_paths: bool,
+    /// Prefix item titles with their outline number
+    number_headings: bool,
+    /// Leave numbered markers in content in place of footnotes
+    footnote_markers: bool,
+    /// Syntax of output
+    format: OutputFormat,
+    /// Title of the standalone document to wrap output in, if any
+    document_title: Option<String>,
 }
 
 impl Extractor {
@@ -250,24 +706,69 @@ impl Extractor {
     pub fn new(
         project: ScrivenerProject,
         bundle: Bundle,
-        folder_specs: HashSet<FolderSpec>,
+        selection: Selection,
         content_specs: HashSet<ContentSpec>,
     ) -> Self {
         Extractor {
             project,
             bundle,
-            folder_specs,
+            selection,
             content_specs,
+            prefix_paths: false,
+            number_headings: false,
+            footnote_markers: false,
+            format: OutputFormat::Text,
+            document_title: None,
         }
     }
 
+    /// Prefix each string with the binder path of its item (titles
+    /// separated by " / ") and a tab
+    pub fn set_prefix_paths(&mut self, prefix_paths: bool) {
+        self.prefix_paths = prefix_paths;
+    }
+
+    /// Prefix item titles with their outline number (e.g. "2.3.1")
+    /// relative to the selected root
+    pub fn set_number_headings(&mut self, number_headings: bool) {
+        self.number_headings = number_headings;
+    }
+
+    /// Leave numbered markers (e.g. "[1]") in content in place of
+    /// inline footnotes
+    pub fn set_footnote_markers(&mut self, footnote_markers: bool) {
+        self.footnote_markers = footnote_markers;
+    }
+
+    /// Render output in the specified syntax
+    pub fn set_format(&mut self, format: OutputFormat) {
+        self.format = format;
+    }
+
+    /// Wrap marked up output in a standalone document with the given
+    /// title, each item in a section headed by its title
+    pub fn set_document_title(&mut self, title: Option<String>) {
+        if title.is_some() {
+            self.content_specs.insert(ContentSpec::Title);
+        }
+        self.document_title = title;
+    }
+
     /// Return an iterator over all selected content
-    pub fn iter(&self) -> ExtractionIterator {
-        ExtractionIterator::new(
+    pub fn iter(&self) -> Result<ExtractionIterator<'_>, ScryError> {
+        let mut iterator = ExtractionIterator::new(
             &self.bundle,
-            binder_iterator(&self.project, self.folder_specs.clone()),
+            select_items(&self.project, &self.bundle, &self.selection)?,
             &self.content_specs,
-        )
+        );
+        iterator.prefix_paths = self.prefix_paths;
+        iterator.number_headings = self.number_headings;
+        iterator.footnote_markers = self.footnote_markers;
+        iterator.format = self.format;
+        if let Some(ref title) = self.document_title {
+            iterator.start_document(title);
+        }
+        Ok(iterator)
     }
 }
 
@@ -276,18 +777,38 @@ pub struct ExtractionIterator<'a> {
     /// Bundle for locating content
     bundle: &'a Bundle,
     /// Where we're up to in the binder
-    binder_iterator: BinderIterator<'a>,
+    binder_iterator: ItemIterator<'a>,
     /// Where we're up to in the current item
     content_iterator: Option<ContentIterator>,
     /// Content to include
     content_specs: &'a HashSet<ContentSpec>,
+    /// Prefix each string with the binder path of its item
+    prefix_paths: bool,
+    /// Prefix for strings of the current item
+    prefix: String,
+    /// Prefix item titles with their outline number
+    number_headings: bool,
+    /// Outline numbers of items so far
+    numbering: OutlineNumbering,
+    /// Leave numbered markers in content in place of footnotes
+    footnote_markers: bool,
+    /// Syntax of output
+    format: OutputFormat,
+    /// Whether output is wrapped in a standalone document
+    document: bool,
+    /// Depths of the items whose document sections are open
+    sections: Vec<usize>,
+    /// Document structure waiting to be output
+    pending: VecDeque<String>,
+    /// Problems worked around in items already finished
+    warnings: Vec<ScryWarning>,
 }
 
 impl<'a> ExtractionIterator<'a> {
     /// Create a new extraction iterator using extractor's settings
     pub fn new(
         bundle: &'a Bundle,
-        binder_iterator: BinderIterator<'a>,
+        binder_iterator: ItemIterator<'a>,
         content_specs: &'a HashSet<ContentSpec>,
     ) -> Self {
         ExtractionIterator {
@@ -295,20 +816,94 @@ impl<'a> ExtractionIterator<'a> {
             binder_iterator,
             content_iterator: None,
             content_specs,
+            prefix_paths: false,
+            prefix: String::new(),
+            number_headings: false,
+            numbering: OutlineNumbering::new(),
+            footnote_markers: false,
+            format: OutputFormat::Text,
+            document: false,
+            sections: vec![],
+            pending: VecDeque::new(),
+            warnings: vec![],
+        }
+    }
+
+    /// Wrap output in a standalone document
+    fn start_document(&mut self, title: &str) {
+        self.document = true;
+        if let Some(markup) = self.format.markup() {
+            self.pending.extend(markup.document_start(title));
+        }
+    }
+
+    /// Queue document structure to close sections at `depth` and
+    /// below, and open one for an item at `depth` if there is one
+    fn queue_sections(&mut self, depth: Option<usize>) {
+        let markup = match self.format.markup() {
+            Some(markup) if self.document => markup,
+            _ => return,
+        };
+        while self
+            .sections
+            .last()
+            .is_some_and(|open| depth.is_none_or(|d| *open >= d))
+        {
+            self.sections.pop();
+            self.pending.extend(markup.section_end());
+        }
+        match depth {
+            Some(depth) => {
+                if let Some(start) = markup.section_start() {
+                    self.pending.push_back(start);
+                    self.sections.push(depth);
+                }
+            }
+            None => {
+                self.pending.extend(markup.document_end());
+                self.document = false;
+            }
         }
     }
 
+    /// Take the problems worked around so far
+    pub fn take_warnings(&mut self) -> Vec<ScryWarning> {
+        let mut warnings = std::mem::take(&mut self.warnings);
+        if let Some(ref mut it) = self.content_iterator {
+            warnings.extend(it.take_warnings());
+        }
+        warnings
+    }
+
     /// Load up the next content iterator
     fn load_content_iterator(&mut self) -> bool {
-        if let Some(item) = self.binder_iterator.next() {
-            self.content_iterator = Some(ContentIterator::new(
+        if let Some(mut finished) = self.content_iterator.take() {
+            self.warnings.extend(finished.take_warnings());
+        }
+        if let Some(entry) = self.binder_iterator.next() {
+            self.queue_sections(Some(entry.depth()));
+            let item = entry.item;
+            if self.prefix_paths {
+                self.prefix = format!("{}\t", entry.path().join(" / "));
+            }
+            let number = self.numbering.number(&entry);
+            let title = if self.number_headings && !number.is_empty() {
+                format!("{} {}", number, item.title)
+            } else {
+                item.title.clone()
+            };
+            let mut content_iterator = ContentIterator::new(
                 item.uuid,
-                item.title.clone(),
-                self.bundle.binder_item_content(&item.uuid),
+                title,
+                self.bundle.binder_item_content(item),
                 self.content_specs,
-            ));
+            );
+            content_iterator.set_footnote_markers(self.footnote_markers);
+            content_iterator.set_format(self.format, entry.depth() + 1);
+            self.content_iterator = Some(content_iterator);
             true
         } else {
+            self.queue_sections(None);
             false
         }
     }
@@ -320,26 +915,140 @@ impl<'a> Iterator for ExtractionIterator<'a> {
     /// Get next item from content iterator unless it is exhausted in
     /// which case load up a content iterator for the next item
     fn next(&mut self) -> Option<Self::Item> {
-        if let Some(ref mut it) = self.content_iterator {
-            if let Some(text) = it.next() {
-                Some(text)
-            } else if self.load_content_iterator() {
-                self.next()
-            } else {
-                None
-            }
+        if let Some(text) = self.pending.pop_front() {
+            Some(text)
+        } else if let Some(text) = self.content_iterator.as_mut().and_then(|it| it.next()) {
+            Some(format!("{}{}", self.prefix, text))
         } else if self.load_content_iterator() {
             self.next()
         } else {
-            None
+            self.pending.pop_front()
+        }
+    }
+}
+
+/// A span of output content that a linked comment is anchored to
+#[derive(Debug, PartialEq)]
+struct CommentAnchor {
+    /// Index of the paragraph in the output content
+    paragraph: usize,
+    /// Character offset of the start of the span
+    start: usize,
+    /// Character offset of the end of the span
+    end: usize,
+    /// The anchored text
+    text: String,
+}
+
+/// Item content as output in JSON: paragraphs with annotations and
+/// footnotes cut out and style tags removed, along with everything
+/// positioned in them
+struct ItemContent {
+    /// Content paragraphs
+    paragraphs: Vec<String>,
+    /// Footnotes cut out of the content
+    footnotes: Vec<annot::Footnote>,
+    /// Spans anchoring linked comments, by comment ID, where they
+    /// survive in the content
+    anchors: HashMap<Uuid, Vec<CommentAnchor>>,
+}
+
+impl ItemContent {
+    /// Parse RTF content, splicing in any linked footnotes among
+    /// `comments` and adding any that are skipped to `warnings`
+    fn parse(
+        data: &[u8],
+        comments: Option<&comments::Comments>,
+        footnote_markers: bool,
+        warnings: &mut Vec<ScryWarning>,
+    ) -> Result<Self, ScryError> {
+        let links = rtf::parse_rtf_anchors(data)?;
+        let paragraphs = comments::LinkedFootnoteAdapter::new(
+            rtf::parse_rtf_anchored(data)?,
+            comments,
+            warnings,
+        );
+        let mut adapter =
+            annot::FootnoteAdapter::new(annot::skip_annotations(paragraphs), footnote_markers);
+        let mut content = ItemContent {
+            paragraphs: vec![],
+            footnotes: vec![],
+            anchors: HashMap::new(),
+        };
+        for (paragraph, line) in adapter.by_ref().enumerate() {
+            let (text, spans) = markup::split_anchors(&tag::strip_tags(line));
+            for span in spans {
+                if let Some(id) = links.get(span.index).and_then(rtf::Anchor::comment_id) {
+                    content.anchors.entry(id).or_default().push(CommentAnchor {
+                        paragraph,
+                        start: span.start,
+                        end: span.end,
+                        text: text
+                            .chars()
+                            .skip(span.start)
+                            .take(span.end - span.start)
+                            .collect(),
+                    });
+                }
+            }
+            content.paragraphs.push(text);
         }
+        content.footnotes = adapter.into_footnotes();
+        Ok(content)
     }
 }
 
-/// Outputs flat list of structured items to stdout as JSON.
+/// Massage an out-of-line comment into a JSON object, including the
+/// spans of content text it is anchored to
+fn comment_object(
+    comment: &comments::Comment,
+    anchors: &HashMap<Uuid, Vec<CommentAnchor>>,
+) -> Result<JsonValue, ScryError> {
+    let mut object = JsonValue::new_object();
+    object.insert("id", comment.id.to_string().to_ascii_uppercase())?;
+    if let Some(ref author) = comment.author {
+        object.insert("author", author.clone())?;
+    }
+    if let Some(ref date) = comment.date {
+        object.insert("date", date.clone())?;
+    }
+    if let Some(color) = comment.color {
+        object.insert("color", color.to_string())?;
+    }
+    object.insert("text", comment.paragraphs()?)?;
+
+    let mut array = JsonValue::new_array();
+    for anchor in anchors.get(&comment.id).into_iter().flatten() {
+        let mut span = JsonValue::new_object();
+        span.insert("paragraph", anchor.paragraph)?;
+        span.insert("start", anchor.start)?;
+        span.insert("end", anchor.end)?;
+        span.insert("text", anchor.text.clone())?;
+        array.push(span)?;
+    }
+    object.insert("anchors", array)?;
+    Ok(object)
+}
+
+/// Create a JSON representation of an inline footnote and its anchor
+fn footnote_object(footnote: &annot::Footnote) -> Result<JsonValue, ScryError> {
+    let mut object = JsonValue::new_object();
+    object.insert("number", footnote.number)?;
+    object.insert("paragraph", footnote.paragraph)?;
+    object.insert("offset", footnote.offset)?;
+    object.insert(
+        "text",
+        markup::strip_marks(tag::strip_tags(footnote.text.clone())),
+    )?;
+    Ok(object)
+}
+
+/// Outputs list of structured items to stdout as JSON.
 ///
-/// Internal item structure is preserved but binder structure is
-/// collapsed into a depth first listing.
+/// Internal item structure is preserved. By default binder structure
+/// is collapsed into a depth first listing; when nested, each item
+/// carries its selected descendants in a `children` array mirroring
+/// the binder.
 ///
 /// (no need to abstract, no need to stream for now)
 pub struct JsonItemiser {
@@ -347,6 +1056,17 @@ pub struct JsonItemiser {
     content_specs: HashSet<ContentSpec>,
     /// items accumulated so far
     items: Vec<JsonValue>,
+    /// binder positions of the accumulated items for rebuilding the
+    /// hierarchy
+    lineage: Vec<Vec<usize>>,
+    /// nest items under their parents rather than listing them flat
+    nested: bool,
+    /// outline numbers of items so far
+    numbering: OutlineNumbering,
+    /// leave numbered markers in content in place of footnotes
+    footnote_markers: bool,
+    /// problems worked around so far
+    warnings: Vec<ScryWarning>,
 }
 
 impl JsonItemiser {
@@ -354,21 +1074,79 @@ impl JsonItemiser {
     pub fn new(content_specs: HashSet<ContentSpec>) -> Self {
         JsonItemiser {
             items: vec![],
+            lineage: vec![],
+            nested: false,
+            numbering: OutlineNumbering::new(),
+            footnote_markers: false,
+            warnings: vec![],
             content_specs,
         }
     }
 
+    /// Nest items in `children` arrays following the binder
+    pub fn set_nested(&mut self, nested: bool) {
+        self.nested = nested;
+    }
+
+    /// Leave numbered markers (e.g. "[1]") in content in place of
+    /// inline footnotes
+    pub fn set_footnote_markers(&mut self, footnote_markers: bool) {
+        self.footnote_markers = footnote_markers;
+    }
+
+    /// Take the problems worked around so far
+    pub fn take_warnings(&mut self) -> Vec<ScryWarning> {
+        std::mem::take(&mut self.warnings)
+    }
+
     /// Accept a binder item and massage into JSON object
     pub fn consume_item(
         &mut self,
-        item: &BinderItem,
+        entry: &BinderEntry,
         folder: &BinderItemFolder,
     ) -> Result<(), ScryError> {
+        let item = entry.item;
         let mut object = JsonValue::new_object();
         // x-scrivener-item links need uppercase GUIDS - might as well
         // ensure it here:
         object.insert("uuid", item.uuid.to_string().to_ascii_uppercase())?;
+        if let Some(ref id) = item.id {
+            object.insert("id", id.clone())?;
+        }
         object.insert("type", item.r#type.to_string())?;
+        object.insert("path", entry.path())?;
+        object.insert("depth", entry.depth())?;
+        let outline = self.numbering.number(entry);
+        if !outline.is_empty() {
+            object.insert("outline", outline)?;
+        }
+        if let Some(created) = item.created {
+            object.insert("created", created.to_rfc3339())?;
+        }
+        if let Some(modified) = item.modified {
+            object.insert("modified", modified.to_rfc3339())?;
+        }
+        // "No Label" and "No Status" (ID -1) are not worth reporting
+        if let Some(label) = item.label.as_ref().filter(|l| l.id >= 0) {
+            object.insert("label", label.name.clone())?;
+        }
+        if let Some(status) = item.status.as_ref().filter(|s| s.id >= 0) {
+            object.insert("status", status.name.clone())?;
+        }
+        if !item.keywords.is_empty() {
+            object.insert("keywords", item.keywords.clone())?;
+        }
+        if !item.custom_metadata.is_empty() {
+            let mut metadata = JsonValue::new_object();
+            for field in &item.custom_metadata {
+                let value: JsonValue = match field.value {
+                    MetaDataValue::Checkbox(b) => b.into(),
+                    ref v => v.to_string().into(),
+                };
+                metadata.insert(&field.title, value)?;
+            }
+            object.insert("metadata", metadata)?;
+        }
 
         if self.content_specs.contains(&ContentSpec::Title) {
             object.insert("title", item.title.clone())?;
@@ -376,19 +1154,36 @@ impl JsonItemiser {
 
         if self.content_specs.contains(&ContentSpec::Synopsis) {
             if let Some(path) = folder.synopsis() {
-                let file = File::open(path)?;
-                let mut content = String::new();
-                io::BufReader::new(file).read_to_string(&mut content)?;
+                let content = String::from_utf8_lossy(&folder.read(path)?).into_owned();
                 object.insert("synopsis", content)?;
             }
         }
 
-        if self.content_specs.contains(&ContentSpec::Content) {
+        let wants_content = self.content_specs.contains(&ContentSpec::Content);
+        let wants_footnotes = self.content_specs.contains(&ContentSpec::Footnotes);
+        let wants_comments = self.content_specs.contains(&ContentSpec::Comments);
+        let mut anchors = HashMap::new();
+        if wants_content || wants_footnotes || wants_comments {
             if let Some(path) = folder.content() {
                 if path.extension() == Some(OsStr::new("rtf")) {
-                    let content: Vec<String> =
-                        annot::skip_annotations(rtf::parse_rtf_file(path)?).collect();
-                    object.insert("content", content)?;
+                    let item_comments = comments::parse_item_footnotes(folder, &mut self.warnings);
+                    let content = ItemContent::parse(
+                        &folder.read(path)?,
+                        item_comments.as_ref(),
+                        self.footnote_markers,
+                        &mut self.warnings,
+                    )?;
+                    if wants_content {
+                        object.insert("content", content.paragraphs)?;
+                    }
+                    if wants_footnotes {
+                        let mut array = JsonValue::new_array();
+                        for footnote in &content.footnotes {
+                            array.push(footnote_object(footnote)?)?;
+                        }
+                        object.insert("footnotes", array)?;
+                    }
+                    anchors = content.anchors;
                 }
             }
         }
@@ -397,7 +1192,7 @@ impl JsonItemiser {
             if let Some(path) = folder.content() {
                 if path.extension() == Some(OsStr::new("rtf")) {
                     let content: Vec<String> =
-                        annot::only_annotations(rtf::parse_rtf_file(path)?).collect();
+                        annot::only_annotations(read_rtf(folder, path)?).collect();
                     object.insert("inlines", content)?;
                 }
             }
@@ -406,25 +1201,323 @@ impl JsonItemiser {
         if self.content_specs.contains(&ContentSpec::Notes) {
             if let Some(path) = folder.notes() {
                 if path.extension() == Some(OsStr::new("rtf")) {
-                    let content: Vec<String> = rtf::parse_rtf_file(path)?.collect();
+                    let content: Vec<String> = read_rtf(folder, path)?.collect();
                     object.insert("notes", content)?;
                 }
             }
         }
 
         if self.content_specs.contains(&ContentSpec::Comments) {
-            // TODO: comments
+            if let Some(comments) = comments::parse_item_comments(folder)? {
+                let mut array = JsonValue::new_array();
+                for comment in comments.comments() {
+                    array.push(comment_object(comment, &anchors)?)?;
+                }
+                object.insert("comments", array)?;
+            }
         }
 
         self.items.push(object);
+        self.lineage.push(entry.position());
         Ok(())
     }
 
+    /// The accumulated items, nested if requested.
+    ///
+    /// When nesting, an item whose parent was not selected is placed
+    /// under its nearest selected ancestor, or at the top level if
+    /// there is none.
+    pub fn into_items(self) -> Result<Vec<JsonValue>, ScryError> {
+        if !self.nested {
+            return Ok(self.items);
+        }
+
+        let positions: HashMap<&[usize], usize> = self
+            .lineage
+            .iter()
+            .enumerate()
+            .map(|(i, position)| (position.as_slice(), i))
+            .collect();
+        let mut children: Vec<Vec<usize>> = vec![vec![]; self.items.len()];
+        let mut roots = vec![];
+        for (i, position) in self.lineage.iter().enumerate() {
+            // ancestors' positions are prefixes, nearest first
+            let parent = (1..position.len())
+                .rev()
+                .find_map(|len| positions.get(&position[..len]));
+            match parent {
+                Some(&parent) => children[parent].push(i),
+                None => roots.push(i),
+            }
+        }
+
+        let mut items: Vec<Option<JsonValue>> = self.items.into_iter().map(Some).collect();
+        roots
+            .into_iter()
+            .map(|i| nest_item(i, &mut items, &children))
+            .collect()
+    }
+
     /// Wrap in an { "items": [...] } object and dump to stdout
     pub fn write_to_stdout(self) -> Result<(), ScryError> {
         let mut wrapper = JsonValue::new_object();
-        wrapper.insert("items", self.items)?;
+        wrapper.insert("items", self.into_items()?)?;
         wrapper.write(&mut stdout())?;
         Ok(())
     }
 }
+
+/// Take item `i` and recursively attach its children
+fn nest_item(
+    i: usize,
+    items: &mut [Option<JsonValue>],
+    children: &[Vec<usize>],
+) -> Result<JsonValue, ScryError> {
+    let mut object = items[i].take().unwrap_or_else(JsonValue::new_object);
+    let mut array = JsonValue::new_array();
+    for &child in &children[i] {
+        array.push(nest_item(child, items, children)?)?;
+    }
+    object.insert("children", array)?;
+    Ok(object)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// Legacy items have no UUIDs so must be told apart by position
+    const LEGACY_PROJECT: &str = r#"<ScrivenerProject Version="1.0">
+    <Binder>
+        <BinderItem ID="0" Type="DraftFolder">
+            <Title>Manuscript</Title>
+            <Children>
+                <BinderItem ID="10" Type="Folder">
+                    <Title>Part One</Title>
+                    <Children>
+                        <BinderItem ID="11" Type="Text"><Title>Chapter One</Title></BinderItem>
+                        <BinderItem ID="12" Type="Text"><Title>Chapter Two</Title></BinderItem>
+                    </Children>
+                </BinderItem>
+                <BinderItem ID="20" Type="Folder">
+                    <Title>Part Two</Title>
+                    <Children>
+                        <BinderItem ID="21" Type="Text"><Title>Chapter Three</Title></BinderItem>
+                    </Children>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>"#;
+
+    #[test]
+    pub fn test_item_content_anchors() {
+        let source = r#"{\rtf1\ansi\ansicpg1252
+\pard \{\\Scrv_annot \\text=aside\\end_Scrv_annot\}\
+<$Scr_Ps::0>Some \{\\Scrv_annot \\text=note\\end_Scrv_annot\}<$Scr_Cs::0>commented-on<!$Scr_Cs::0> {\field{\*\fldinst{HYPERLINK "scrivcmt://3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6"}}{\fldrslt text}}.\
+}"#;
+        let mut warnings = vec![];
+        let content = ItemContent::parse(source.as_bytes(), None, false, &mut warnings).unwrap();
+        // the annotation splits the paragraph
+        assert_eq!(content.paragraphs, vec!["Some ", "commented-on text."]);
+
+        let id = Uuid::parse_str("3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6").unwrap();
+        assert_eq!(
+            content.anchors[&id],
+            vec![CommentAnchor {
+                paragraph: 1,
+                start: 13,
+                end: 17,
+                text: "text".to_string()
+            }]
+        );
+    }
+
+    #[test]
+    pub fn test_nested_legacy_items() {
+        let project = ScrivenerProject::parse(LEGACY_PROJECT.as_bytes()).unwrap();
+        let bundle = Bundle::new("/nonexistent", ProjectFormat::Legacy);
+        let mut content_specs = HashSet::new();
+        content_specs.insert(ContentSpec::Title);
+
+        let mut itemiser = JsonItemiser::new(content_specs);
+        itemiser.set_nested(true);
+        for entry in project.entries() {
+            itemiser
+                .consume_item(&entry, &bundle.binder_item_content(entry.item))
+                .unwrap();
+        }
+        let items = itemiser.into_items().unwrap();
+
+        assert_eq!(items.len(), 1);
+        let parts = &items[0]["children"];
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0]["children"].len(), 2);
+        assert_eq!(parts[0]["children"][1]["title"], "Chapter Two");
+        assert_eq!(parts[1]["children"].len(), 1);
+        assert_eq!(parts[1]["children"][0]["title"], "Chapter Three");
+    }
+
+    #[test]
+    pub fn test_nested_items() {
+        let source = r#"<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder">
+            <Title>Draft</Title>
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002" Type="Folder">
+                    <Title>Part One</Title>
+                    <Children>
+                        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003" Type="Text">
+                            <Title>Scene One</Title>
+                        </BinderItem>
+                    </Children>
+                </BinderItem>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000004" Type="Text">
+                    <Title>Scene Two</Title>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>"#;
+        let project = ScrivenerProject::parse(source.as_bytes()).unwrap();
+        let bundle = Bundle::new("/nonexistent", ProjectFormat::Current);
+        let mut content_specs = HashSet::new();
+        content_specs.insert(ContentSpec::Title);
+
+        // skip Part One to check its child moves up to Draft
+        let mut itemiser = JsonItemiser::new(content_specs);
+        itemiser.set_nested(true);
+        for entry in project.entries().filter(|e| e.item.title != "Part One") {
+            itemiser
+                .consume_item(&entry, &bundle.binder_item_content(entry.item))
+                .unwrap();
+        }
+        let items = itemiser.into_items().unwrap();
+
+        assert_eq!(items.len(), 1);
+        assert_eq!(items[0]["title"], "Draft");
+        let children = &items[0]["children"];
+        assert_eq!(children.len(), 2);
+        assert_eq!(children[0]["title"], "Scene One");
+        assert_eq!(children[0]["depth"], 2);
+        assert_eq!(children[0]["outline"], "1.1");
+        assert_eq!(children[1]["outline"], "2");
+        assert_eq!(children[1]["title"], "Scene Two");
+        assert_eq!(children[1]["children"].len(), 0);
+    }
+
+    #[test]
+    pub fn test_path_patterns_skip_trash() {
+        let source = r#"<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder">
+            <Title>Draft</Title>
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002" Type="Text">
+                    <Title>Ch 1</Title>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003" Type="TrashFolder">
+            <Title>Trash</Title>
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000004" Type="Text">
+                    <Title>Ch 2</Title>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>"#;
+        let project = ScrivenerProject::parse(source.as_bytes()).unwrap();
+        let titles = |pattern: &str| -> Vec<String> {
+            let specs = vec![FolderSpec::Path(pattern.parse().unwrap())];
+            binder_iterator(&project, specs.into_iter().collect())
+                .unwrap()
+                .map(|e| e.item.title.clone())
+                .collect()
+        };
+        assert_eq!(titles("**"), vec!["Draft", "Ch 1"]);
+        assert_eq!(titles("*/Ch*"), vec!["Ch 1"]);
+        assert_eq!(titles("Trash/**"), vec!["Trash", "Ch 2"]);
+        assert!(binder_iterator(
+            &project,
+            vec![FolderSpec::Path("*/Ch 2".parse().unwrap())]
+                .into_iter()
+                .collect()
+        )
+        .is_err());
+    }
+
+    #[test]
+    pub fn test_legacy_outline_numbering() {
+        let project = ScrivenerProject::parse(LEGACY_PROJECT.as_bytes()).unwrap();
+        let mut numbering = OutlineNumbering::new();
+        let numbers: Vec<_> = project
+            .entries()
+            .map(|e| format!("{} {}", numbering.number(&e), e.item.title))
+            .collect();
+        assert_eq!(
+            numbers,
+            vec![
+                " Manuscript",
+                "1 Part One",
+                "1.1 Chapter One",
+                "1.2 Chapter Two",
+                "2 Part Two",
+                "2.1 Chapter Three"
+            ]
+        );
+    }
+
+    #[test]
+    pub fn test_outline_numbering() {
+        let source = r#"<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder">
+            <Title>Draft</Title>
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002" Type="Folder">
+                    <Title>Part One</Title>
+                </BinderItem>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003" Type="Folder">
+                    <Title>Part Two</Title>
+                    <Children>
+                        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000004" Type="Text">
+                            <Title>Scene One</Title>
+                        </BinderItem>
+                        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000005" Type="Text">
+                            <Title>Scene Two</Title>
+                        </BinderItem>
+                    </Children>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>"#;
+        let project = ScrivenerProject::parse(source.as_bytes()).unwrap();
+
+        let mut numbering = OutlineNumbering::new();
+        let numbers: Vec<_> = project.entries().map(|e| numbering.number(&e)).collect();
+        assert_eq!(numbers, vec!["", "1", "2", "2.1", "2.2"]);
+
+        // numbers are relative to the selected root and skip nothing
+        // when siblings are filtered out
+        let mut numbering = OutlineNumbering::new();
+        let numbers: Vec<_> = project
+            .entries()
+            .filter(|e| e.depth() > 1 && e.item.title != "Scene One")
+            .map(|e| numbering.number(&e))
+            .collect();
+        assert_eq!(numbers, vec![""]);
+
+        let mut numbering = OutlineNumbering::new();
+        let numbers: Vec<_> = project
+            .entries()
+            .skip(2)
+            .filter(|e| e.item.title != "Scene One")
+            .map(|e| numbering.number(&e))
+            .collect();
+        assert_eq!(numbers, vec!["", "2"]);
+    }
+}
@@ -0,0 +1,238 @@
+//! Glob patterns matched against binder paths
+//!
+//! A pattern is a sequence of `/` separated segments, each matched
+//! against the title of an item at successive depths of the binder,
+//! starting from the top-level folders. Within a segment `*` matches
+//! any run of characters, `?` a single character and `[...]` a
+//! character class (`[!...]` negated). A segment of `**` matches any
+//! number of items, including none, so `Draft/Part Two/**` matches
+//! Part Two and everything beneath it. A backslash escapes the next
+//! character, e.g. `Notes\/Ideas` for a title containing a slash.
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Error in a binder path pattern
+#[derive(Error, Debug)]
+#[error("invalid path pattern {pattern:?}: {reason}")]
+pub struct PatternError {
+    pattern: String,
+    reason: &'static str,
+}
+
+/// An element of a title glob
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Token {
+    /// A literal character
+    Char(char),
+    /// `?`: any single character
+    One,
+    /// `*`: any run of characters
+    Any,
+    /// `[...]`: a character from the ranges (or not, if negated)
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Token {
+    /// Returns true if a single character token matches `c`
+    fn matches_char(&self, c: char) -> bool {
+        match self {
+            Token::Char(t) => *t == c,
+            Token::One => true,
+            Token::Any => false,
+            Token::Class { negated, ranges } => {
+                ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != *negated
+            }
+        }
+    }
+}
+
+/// One segment of a binder path pattern
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum Segment {
+    /// `**`: any number of items
+    AnyDepth,
+    /// A glob matched against a single title
+    Title(Vec<Token>),
+}
+
+/// A glob pattern matched against the titles on a binder path
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct BinderPattern {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl BinderPattern {
+    /// Returns true if the path of titles (top-level folder first)
+    /// matches the pattern
+    pub fn matches<S: AsRef<str>>(&self, path: &[S]) -> bool {
+        match_segments(&self.segments, path)
+    }
+
+    /// Returns true if the first segment is exactly `title`, without
+    /// wildcards
+    pub fn names_top_level(&self, title: &str) -> bool {
+        match self.segments.first() {
+            Some(Segment::Title(tokens)) => {
+                let literal: Option<String> = tokens
+                    .iter()
+                    .map(|t| match t {
+                        Token::Char(c) => Some(*c),
+                        _ => None,
+                    })
+                    .collect();
+                literal.as_deref() == Some(title)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Match remaining segments against remaining titles
+fn match_segments<S: AsRef<str>>(segments: &[Segment], path: &[S]) -> bool {
+    match segments.split_first() {
+        None => path.is_empty(),
+        Some((Segment::AnyDepth, rest)) => {
+            (0..=path.len()).any(|skip| match_segments(rest, &path[skip..]))
+        }
+        Some((Segment::Title(tokens), rest)) => match path.split_first() {
+            Some((title, path)) => {
+                let title: Vec<char> = title.as_ref().chars().collect();
+                match_title(tokens, &title) && match_segments(rest, path)
+            }
+            None => false,
+        },
+    }
+}
+
+/// Match remaining glob tokens against remaining title characters
+fn match_title(tokens: &[Token], title: &[char]) -> bool {
+    match tokens.split_first() {
+        None => title.is_empty(),
+        Some((Token::Any, rest)) => (0..=title.len()).any(|skip| match_title(rest, &title[skip..])),
+        Some((token, rest)) => match title.split_first() {
+            Some((c, title)) => token.matches_char(*c) && match_title(rest, title),
+            None => false,
+        },
+    }
+}
+
+/// Split a pattern into segments on unescaped slashes
+fn split_segments(pattern: &str) -> Vec<String> {
+    let mut segments = vec![String::new()];
+    let mut chars = pattern.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                let current = segments.last_mut().unwrap();
+                current.push(c);
+                current.extend(chars.next());
+            }
+            '/' => segments.push(String::new()),
+            _ => segments.last_mut().unwrap().push(c),
+        }
+    }
+    segments
+}
+
+/// Parse the glob for a single title
+fn parse_title(glob: &str) -> Result<Vec<Token>, &'static str> {
+    let mut tokens = vec![];
+    let mut chars = glob.chars().peekable();
+    while let Some(c) = chars.next() {
+        tokens.push(match c {
+            '*' => Token::Any,
+            '?' => Token::One,
+            '\\' => Token::Char(chars.next().ok_or("trailing backslash")?),
+            '[' => {
+                let negated = chars.next_if_eq(&'!').is_some();
+                let mut ranges = vec![];
+                loop {
+                    let lo = match chars.next().ok_or("unclosed character class")? {
+                        ']' if !ranges.is_empty() => break,
+                        '\\' => chars.next().ok_or("trailing backslash")?,
+                        c => c,
+                    };
+                    let hi = match chars.next_if_eq(&'-') {
+                        Some(_) => chars.next().ok_or("unclosed character class")?,
+                        None => lo,
+                    };
+                    ranges.push((lo, hi));
+                }
+                Token::Class { negated, ranges }
+            }
+            c => Token::Char(c),
+        });
+    }
+    Ok(tokens)
+}
+
+impl FromStr for BinderPattern {
+    type Err = PatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments = split_segments(s.trim_matches('/'))
+            .into_iter()
+            .map(|segment| match segment.as_str() {
+                "**" => Ok(Segment::AnyDepth),
+                glob => parse_title(glob).map(Segment::Title),
+            })
+            .collect::<Result<_, _>>()
+            .map_err(|reason| PatternError {
+                pattern: s.to_string(),
+                reason,
+            })?;
+
+        Ok(BinderPattern {
+            source: s.to_string(),
+            segments,
+        })
+    }
+}
+
+impl fmt::Display for BinderPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.source)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, path: &[&str]) -> bool {
+        pattern.parse::<BinderPattern>().unwrap().matches(path)
+    }
+
+    #[test]
+    pub fn test_binder_pattern() {
+        assert!(matches("Draft/Part Two", &["Draft", "Part Two"]));
+        assert!(!matches("Draft/Part Two", &["Draft", "Part Two", "Ch 1"]));
+        assert!(matches("Draft/Part Two/**", &["Draft", "Part Two"]));
+        assert!(matches(
+            "Draft/Part Two/**",
+            &["Draft", "Part Two", "Ch 1", "Scene"]
+        ));
+        assert!(!matches(
+            "Draft/Part Two/**",
+            &["Draft", "Part One", "Ch 1"]
+        ));
+        assert!(matches("**/Ch*/*", &["Draft", "Part One", "Ch 1", "Scene"]));
+        assert!(!matches("**/Ch*/*", &["Draft", "Part One", "Ch 1"]));
+        assert!(matches("Draft/Ch ?", &["Draft", "Ch 7"]));
+        assert!(matches("Draft/Ch [!0-4]", &["Draft", "Ch 7"]));
+        assert!(!matches("Draft/Ch [!0-4]", &["Draft", "Ch 3"]));
+        assert!(matches("Notes\\/Ideas (1)", &["Notes/Ideas (1)"]));
+        assert!(matches("Draft/[]x]", &["Draft", "]"]));
+        let trash = |p: &str| p.parse::<BinderPattern>().unwrap().names_top_level("Trash");
+        assert!(trash("Trash/**"));
+        assert!(!trash("Tra*/**"));
+        assert!(!trash("**"));
+        assert!("Draft/[".parse::<BinderPattern>().is_err());
+        assert!("Draft/x\\".parse::<BinderPattern>().is_err());
+    }
+}
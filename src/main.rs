@@ -2,18 +2,26 @@
 
 pub mod annot;
 pub mod bundle;
+pub mod comments;
 pub mod error;
+pub mod expr;
 pub mod extract;
+pub mod glob;
+pub mod markup;
 pub mod options;
 pub mod rtf;
 pub mod scrivx;
+pub mod search;
+pub mod storage;
 pub mod tag;
+pub mod tree;
+pub mod xml;
 
-use std::fs::File;
-
-use error::{Result, ScryError};
-use extract::binder_iterator;
+use error::{Result, ScryError, ScryWarning};
+use extract::select_items;
 use extract::JsonItemiser;
+use extract::OutputFormat;
+use std::io::stdout;
 use structopt::StructOpt;
 
 fn main() {
@@ -23,28 +31,64 @@ fn main() {
     }
 }
 
+/// Report problems that extraction worked around
+fn report_warnings(warnings: Vec<ScryWarning>) {
+    for warning in warnings {
+        eprintln!("Warning: {}", warning);
+    }
+}
+
 /// Run extraction capturing error for reporting
 fn try_main(opts: &options::Opt) -> Result<()> {
+    if opts.standalone() && opts.format() != OutputFormat::Html {
+        return Err(ScryError::StandaloneRequiresHtml);
+    }
     let project_file = opts.project_file().ok_or(ScryError::CannotLocateScrivx)?;
-    let scrivx = File::open(&project_file)?;
-    let directory = project_file.parent().ok_or(ScryError::CannotLocateBundle)?;
-    let project = scrivx::ScrivenerProject::parse(scrivx)?;
-    let bundle = bundle::Bundle::new(directory);
+    let (project, mut bundle) = bundle::open_project(&project_file)?;
+    bundle.set_force(opts.force());
+    if let Some(lock) = bundle.lock() {
+        if !opts.force() {
+            eprintln!(
+                "Warning: project is open in Scrivener: {}; content may be incomplete",
+                lock
+            );
+        }
+    }
 
     if opts.itemise() {
-        let items = binder_iterator(&project, opts.folder_specs());
+        let items = select_items(&project, &bundle, &opts.selection())?;
         let mut itemiser = JsonItemiser::new(opts.content_specs());
-        for item in items {
-            let folder = bundle.binder_item_content(&item.uuid);
-            itemiser.consume_item(item, &folder)?;
+        itemiser.set_nested(opts.nested());
+        itemiser.set_footnote_markers(opts.footnote_markers());
+        for entry in items {
+            let folder = bundle.binder_item_content(entry.item);
+            itemiser.consume_item(&entry, &folder)?;
+            report_warnings(itemiser.take_warnings());
         }
         itemiser.write_to_stdout()?;
+    } else if opts.tree() {
+        let items = select_items(&project, &bundle, &opts.selection())?;
+        tree::write_tree(&mut stdout().lock(), &bundle, items, &opts.tree_options())?;
     } else {
-        let extractor =
-            extract::Extractor::new(project, bundle, opts.folder_specs(), opts.content_specs());
-        for text in extractor.iter() {
+        let mut extractor =
+            extract::Extractor::new(project, bundle, opts.selection(), opts.content_specs());
+        extractor.set_prefix_paths(opts.prefix_paths());
+        extractor.set_number_headings(opts.number_headings());
+        extractor.set_footnote_markers(opts.footnote_markers());
+        extractor.set_format(opts.format());
+        if opts.standalone() {
+            let title = project_file
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            extractor.set_document_title(Some(title));
+        }
+        let mut lines = extractor.iter()?;
+        while let Some(text) = lines.next() {
+            report_warnings(lines.take_warnings());
             println!("{}", text);
         }
+        report_warnings(lines.take_warnings());
     }
 
     Ok(())
@@ -0,0 +1,620 @@
+//! Rendering of paragraph text with its formatting
+//!
+//! When asked to (see `rtf::parse_rtf_marked`), the RTF engine embeds
+//! a mark in paragraph text wherever the character formatting
+//! changes, and around hyperlinks. It can also mark link anchors
+//! (see `rtf::parse_rtf_anchored`) so that their positions can be
+//! recovered once content is adapted. Marks are characters from the
+//! Unicode private use area so they pass untouched through the
+//! annotation and footnote adapters, which only look for Scrivener's
+//! own syntax. Inline annotations can be marked likewise (see
+//! `annot::mark_annotations`).
+//!
+//! A `Markup` then renders a paragraph, marks, Scrivener heading tags
+//! (`<$Scr_H::N>`) and all, into a target syntax such as Markdown or
+//! HTML.
+use crate::tag;
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// First of the sixteen marks encoding a `Format`
+const FORMAT_MARK_BASE: u32 = 0xF700;
+/// Starts a hyperlink, followed by the target
+pub const LINK_OPEN: char = '\u{F710}';
+/// Separates a hyperlink's target from its text
+pub const LINK_TEXT: char = '\u{F711}';
+/// Ends a hyperlink
+pub const LINK_CLOSE: char = '\u{F712}';
+/// Starts an inline annotation
+pub const ANNOTATION_OPEN: char = '\u{F720}';
+/// Ends an inline annotation
+pub const ANNOTATION_CLOSE: char = '\u{F721}';
+/// Starts a link anchor, followed by its index
+pub const ANCHOR_OPEN: char = '\u{F730}';
+/// Separates a link anchor's index from its text
+pub const ANCHOR_TEXT: char = '\u{F731}';
+/// Ends a link anchor
+pub const ANCHOR_CLOSE: char = '\u{F732}';
+
+/// Character formatting in effect for a run of text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Format {
+    pub bold: bool,
+    pub italic: bool,
+    pub strike: bool,
+    pub underline: bool,
+}
+
+impl Format {
+    /// The mark embedded in text to switch to this format
+    pub fn mark(&self) -> char {
+        let bits = self.bold as u32
+            | (self.italic as u32) << 1
+            | (self.strike as u32) << 2
+            | (self.underline as u32) << 3;
+        char::from_u32(FORMAT_MARK_BASE + bits).unwrap()
+    }
+
+    /// The format a mark switches to, if `c` is a mark
+    pub fn from_mark(c: char) -> Option<Format> {
+        let bits = (c as u32)
+            .checked_sub(FORMAT_MARK_BASE)
+            .filter(|b| *b < 16)?;
+        Some(Format {
+            bold: bits & 1 != 0,
+            italic: bits & 2 != 0,
+            strike: bits & 4 != 0,
+            underline: bits & 8 != 0,
+        })
+    }
+
+    /// The styles switched on, outermost first
+    fn styles(&self) -> Vec<Style> {
+        [
+            (self.bold, Style::Bold),
+            (self.italic, Style::Italic),
+            (self.strike, Style::Strike),
+            (self.underline, Style::Underline),
+        ]
+        .iter()
+        .filter(|(on, _)| *on)
+        .map(|(_, style)| *style)
+        .collect()
+    }
+}
+
+/// A single character style
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Style {
+    Bold,
+    Italic,
+    Strike,
+    Underline,
+}
+
+/// Marks to embed before and after a hyperlink's text
+pub fn link_marks(target: &str) -> (String, char) {
+    (format!("{}{}{}", LINK_OPEN, target, LINK_TEXT), LINK_CLOSE)
+}
+
+/// Whether a hyperlink target is rendered as a link: only web and
+/// mail links are; others (e.g. `javascript:` or Scrivener's internal
+/// links) are left as plain text
+pub fn is_linkable(target: &str) -> bool {
+    let scheme = target
+        .split_once(':')
+        .map(|(scheme, _)| scheme.trim().to_ascii_lowercase());
+    matches!(scheme.as_deref(), Some("http" | "https" | "mailto"))
+}
+
+/// Percent-encode the characters that would end a link destination
+/// in angle brackets
+fn encode_destination(target: &str) -> String {
+    let mut encoded = String::with_capacity(target.len());
+    for c in target.chars() {
+        if c == '<' || c == '>' || c.is_whitespace() {
+            let mut buf = [0; 4];
+            for b in c.encode_utf8(&mut buf).bytes() {
+                encoded.push_str(&format!("%{:02X}", b));
+            }
+        } else {
+            encoded.push(c);
+        }
+    }
+    encoded
+}
+
+/// Marks to embed before and after the text of the link anchor with
+/// index `index`
+pub fn anchor_marks(index: usize) -> (String, char) {
+    (
+        format!("{}{}{}", ANCHOR_OPEN, index, ANCHOR_TEXT),
+        ANCHOR_CLOSE,
+    )
+}
+
+/// The position of a link anchor in a paragraph
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct AnchorSpan {
+    /// Index of the anchor among those of the RTF it came from
+    pub index: usize,
+    /// Character offset of the start of the anchored text
+    pub start: usize,
+    /// Character offset of the end of the anchored text
+    pub end: usize,
+}
+
+/// Remove link anchor marks from text, returning the remaining text
+/// and the positions in it of the anchors that were marked
+pub fn split_anchors(line: &str) -> (String, Vec<AnchorSpan>) {
+    let mut text = String::new();
+    let mut spans = vec![];
+    let mut open: Vec<(Option<usize>, usize)> = vec![];
+    let mut offset = 0;
+    let mut chars = line.chars();
+
+    while let Some(c) = chars.next() {
+        if c == ANCHOR_OPEN {
+            let index: String = chars.by_ref().take_while(|c| *c != ANCHOR_TEXT).collect();
+            open.push((index.parse().ok(), offset));
+        } else if c == ANCHOR_CLOSE {
+            if let Some((Some(index), start)) = open.pop() {
+                spans.push(AnchorSpan {
+                    index,
+                    start,
+                    end: offset,
+                });
+            }
+        } else {
+            text.push(c);
+            offset += 1;
+        }
+    }
+    spans.sort_by_key(|span| span.index);
+    (text, spans)
+}
+
+/// A run of text of uniform formatting
+#[derive(Debug, PartialEq)]
+pub struct Run {
+    pub format: Format,
+    /// Target if the run is the text of a hyperlink
+    pub link: Option<String>,
+    pub text: String,
+}
+
+/// Split text carrying marks into runs
+pub fn runs(line: &str) -> Vec<Run> {
+    let mut runs = vec![];
+    let mut format = Format::default();
+    let mut link: Option<String> = None;
+    let mut text = String::new();
+    let mut chars = line.chars();
+
+    let mut flush = |format: Format, link: &Option<String>, text: &mut String| {
+        if !text.is_empty() {
+            runs.push(Run {
+                format,
+                link: link.clone(),
+                text: std::mem::take(text),
+            });
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        if let Some(next) = Format::from_mark(c) {
+            flush(format, &link, &mut text);
+            format = next;
+        } else if c == LINK_OPEN {
+            flush(format, &link, &mut text);
+            link = Some(chars.by_ref().take_while(|c| *c != LINK_TEXT).collect());
+        } else if c == LINK_CLOSE {
+            flush(format, &link, &mut text);
+            link = None;
+        } else if c == ANCHOR_OPEN {
+            chars
+                .by_ref()
+                .take_while(|c| *c != ANCHOR_TEXT)
+                .for_each(drop);
+        } else if c != ANNOTATION_OPEN && c != ANNOTATION_CLOSE && c != ANCHOR_CLOSE {
+            text.push(c);
+        }
+    }
+    flush(format, &link, &mut text);
+    runs
+}
+
+/// Remove any marks (and link targets) from text
+pub fn strip_marks(line: String) -> String {
+    if line.chars().any(|c| ('\u{F700}'..='\u{F7FF}').contains(&c)) {
+        runs(&line).into_iter().map(|run| run.text).collect()
+    } else {
+        line
+    }
+}
+
+/// Split marked annotations out of a paragraph, returning the
+/// remaining content and the annotations
+///
+/// Each annotation starts with the formatting in effect where it
+/// was, as does the content following it.
+pub fn split_annotations(line: &str) -> (String, Vec<String>) {
+    let mut content = String::new();
+    let mut annotations: Vec<String> = vec![];
+    let mut format = Format::default();
+    let mut in_annotation = false;
+
+    for c in line.chars() {
+        if c == ANNOTATION_OPEN {
+            in_annotation = true;
+            annotations.push(format.mark().to_string());
+        } else if c == ANNOTATION_CLOSE {
+            in_annotation = false;
+            content.push(format.mark());
+        } else {
+            if let Some(next) = Format::from_mark(c) {
+                format = next;
+            }
+            match annotations.last_mut() {
+                Some(annotation) if in_annotation => annotation.push(c),
+                _ => content.push(c),
+            }
+        }
+    }
+    (content, annotations)
+}
+
+lazy_static! {
+    static ref HEADING_TAG: Regex = Regex::new(r"<\$Scr_H::(\d+)>").unwrap();
+    static ref ORDERED_LIST_MARKER: Regex = Regex::new(r"^(\d+)([.)])(\s|$)").unwrap();
+}
+
+/// The heading level Scrivener has tagged a paragraph with, if any
+pub fn heading_level(line: &str) -> Option<usize> {
+    HEADING_TAG
+        .captures(line)
+        .and_then(|c| c[1].parse().ok())
+        .map(|level: usize| level.clamp(1, 6))
+}
+
+/// A target syntax for rendering formatted text
+pub trait Markup {
+    /// Opening syntax for a style
+    fn open(&self, style: Style) -> &'static str;
+    /// Closing syntax for a style
+    fn close(&self, style: Style) -> &'static str;
+    /// Escape plain text
+    fn escape(&self, text: &str) -> String;
+    /// Wrap rendered text as a hyperlink
+    fn link(&self, target: &str, content: String) -> String;
+    /// Wrap rendered paragraph content as a paragraph or heading
+    fn block(&self, heading: Option<usize>, content: String) -> String;
+    /// Wrap a rendered annotation as a block
+    fn aside(&self, content: String) -> String;
+    /// Combine the blocks rendered from one paragraph into a single
+    /// string of output
+    fn join(&self, blocks: Vec<String>) -> String;
+
+    /// Opening of a standalone document, if the syntax needs one
+    fn document_start(&self, _title: &str) -> Option<String> {
+        None
+    }
+
+    /// Close of a standalone document
+    fn document_end(&self) -> Option<String> {
+        None
+    }
+
+    /// Opening of an item's section in a standalone document
+    fn section_start(&self) -> Option<String> {
+        None
+    }
+
+    /// Close of an item's section in a standalone document
+    fn section_end(&self) -> Option<String> {
+        None
+    }
+
+    /// Render a paragraph of text carrying marks and Scrivener tags,
+    /// followed by any annotations it contains
+    fn paragraph(&self, line: String) -> String {
+        let (content, annotations) = split_annotations(&line);
+        let mut blocks = vec![];
+        if !strip_marks(content.clone()).trim().is_empty() || annotations.is_empty() {
+            let heading = heading_level(&content);
+            blocks.push(self.block(heading, self.inline(&tag::strip_tags(content))));
+        }
+        for annotation in annotations {
+            blocks.push(self.aside(self.inline(&tag::strip_tags(annotation))));
+        }
+        self.join(blocks)
+    }
+
+    /// Render a heading, e.g. an item title, at a given level
+    fn heading(&self, level: usize, text: &str) -> String {
+        let content = self.inline(text);
+        self.join(vec![self.block(Some(level.clamp(1, 6)), content)])
+    }
+
+    /// Render text carrying formatting marks
+    ///
+    /// Styles are nested in a fixed order and whitespace at the edge
+    /// of a styled run is moved outside it (`*word* ` rather than
+    /// `*word *`) as Markdown requires.
+    fn inline(&self, text: &str) -> String {
+        let mut out = String::new();
+        let mut open: Vec<Style> = vec![];
+        let mut pending_space = String::new();
+
+        for run in runs(text) {
+            if run.text.trim().is_empty() {
+                pending_space.push_str(&run.text);
+                continue;
+            }
+
+            let styles = run.format.styles();
+            let keep = open.iter().zip(&styles).take_while(|(a, b)| a == b).count();
+            while open.len() > keep {
+                out.push_str(self.close(open.pop().unwrap()));
+            }
+
+            let body = run.text.trim_start();
+            pending_space.push_str(&run.text[..run.text.len() - body.len()]);
+            out.push_str(&self.escape(&std::mem::take(&mut pending_space)));
+
+            for style in &styles[keep..] {
+                out.push_str(self.open(*style));
+                open.push(*style);
+            }
+
+            let trimmed = body.trim_end();
+            match run.link {
+                Some(ref target) if is_linkable(target) => {
+                    out.push_str(&self.link(target, self.escape(trimmed)))
+                }
+                _ => out.push_str(&self.escape(trimmed)),
+            }
+            pending_space.push_str(&body[trimmed.len()..]);
+        }
+
+        while let Some(style) = open.pop() {
+            out.push_str(self.close(style));
+        }
+        out.push_str(&self.escape(&pending_space));
+        out
+    }
+}
+
+/// Pandoc-flavoured Markdown
+pub struct Markdown;
+
+impl Markup for Markdown {
+    fn open(&self, style: Style) -> &'static str {
+        match style {
+            Style::Bold => "**",
+            Style::Italic => "*",
+            Style::Strike => "~~",
+            Style::Underline => "[",
+        }
+    }
+
+    fn close(&self, style: Style) -> &'static str {
+        match style {
+            Style::Bold => "**",
+            Style::Italic => "*",
+            Style::Strike => "~~",
+            Style::Underline => "]{.underline}",
+        }
+    }
+
+    fn escape(&self, text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            if matches!(c, '\\' | '*' | '_' | '`' | '~' | '[' | ']' | '<') {
+                escaped.push('\\');
+            }
+            escaped.push(c);
+        }
+        escaped
+    }
+
+    fn link(&self, target: &str, content: String) -> String {
+        format!("[{}](<{}>)", content, encode_destination(target))
+    }
+
+    /// A paragraph which would otherwise be mistaken for a heading,
+    /// block quote or list item has its leading marker escaped
+    fn block(&self, heading: Option<usize>, content: String) -> String {
+        match heading {
+            Some(level) => format!("{} {}", "#".repeat(level), content),
+            None if content.starts_with(['#', '>', '-', '+']) => format!("\\{}", content),
+            None => ORDERED_LIST_MARKER
+                .replace(&content, "$1\\$2$3")
+                .into_owned(),
+        }
+    }
+
+    fn aside(&self, content: String) -> String {
+        format!("> {}", content)
+    }
+
+    /// Blocks are separated, and followed, by a blank line
+    fn join(&self, blocks: Vec<String>) -> String {
+        format!("{}\n", blocks.join("\n\n"))
+    }
+}
+
+/// Semantic HTML
+pub struct Html;
+
+impl Markup for Html {
+    fn open(&self, style: Style) -> &'static str {
+        match style {
+            Style::Bold => "<strong>",
+            Style::Italic => "<em>",
+            Style::Strike => "<s>",
+            Style::Underline => "<u>",
+        }
+    }
+
+    fn close(&self, style: Style) -> &'static str {
+        match style {
+            Style::Bold => "</strong>",
+            Style::Italic => "</em>",
+            Style::Strike => "</s>",
+            Style::Underline => "</u>",
+        }
+    }
+
+    fn escape(&self, text: &str) -> String {
+        let mut escaped = String::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '&' => escaped.push_str("&amp;"),
+                '<' => escaped.push_str("&lt;"),
+                '>' => escaped.push_str("&gt;"),
+                '"' => escaped.push_str("&quot;"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    fn link(&self, target: &str, content: String) -> String {
+        format!("<a href=\"{}\">{}</a>", self.escape(target), content)
+    }
+
+    fn block(&self, heading: Option<usize>, content: String) -> String {
+        match heading {
+            Some(level) => format!("<h{0}>{1}</h{0}>", level, content),
+            None => format!("<p>{}</p>", content),
+        }
+    }
+
+    fn aside(&self, content: String) -> String {
+        format!("<aside>{}</aside>", content)
+    }
+
+    fn join(&self, blocks: Vec<String>) -> String {
+        blocks.join("\n")
+    }
+
+    fn document_start(&self, title: &str) -> Option<String> {
+        Some(format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n</head>\n<body>",
+            self.escape(title)
+        ))
+    }
+
+    fn document_end(&self) -> Option<String> {
+        Some("</body>\n</html>".to_string())
+    }
+
+    fn section_start(&self) -> Option<String> {
+        Some("<section>".to_string())
+    }
+
+    fn section_end(&self) -> Option<String> {
+        Some("</section>".to_string())
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    fn marked(parts: &[(Format, &str)]) -> String {
+        parts
+            .iter()
+            .map(|(format, text)| format!("{}{}", format.mark(), text))
+            .collect()
+    }
+
+    #[test]
+    pub fn test_markdown() {
+        let italic = Format {
+            italic: true,
+            ..Default::default()
+        };
+        let bold_italic = Format {
+            bold: true,
+            ..italic
+        };
+        let line = marked(&[
+            (Format::default(), "A "),
+            (italic, "very "),
+            (bold_italic, "bold"),
+            (Format::default(), " claim * 2."),
+        ]);
+        assert_eq!(
+            Markdown.paragraph(line),
+            "A *very* ***bold*** claim \\* 2.\n"
+        );
+
+        assert_eq!(
+            Markdown.paragraph("<$Scr_H::2><$Scr_Ps::0>Part One<!$Scr_H::2>".to_string()),
+            "## Part One\n"
+        );
+        assert_eq!(Markdown.paragraph("#hashtag".to_string()), "\\#hashtag\n");
+        assert_eq!(Markdown.paragraph("> not".to_string()), "\\> not\n");
+        assert_eq!(Markdown.paragraph("- not".to_string()), "\\- not\n");
+        assert_eq!(Markdown.paragraph("+ not".to_string()), "\\+ not\n");
+        assert_eq!(Markdown.paragraph("* not".to_string()), "\\* not\n");
+        assert_eq!(Markdown.paragraph("1. not".to_string()), "1\\. not\n");
+        assert_eq!(Markdown.paragraph("12) not".to_string()), "12\\) not\n");
+        assert_eq!(Markdown.paragraph("1.5 litres".to_string()), "1.5 litres\n");
+        assert_eq!(Markdown.paragraph("a <b> c".to_string()), "a \\<b> c\n");
+        assert_eq!(Markdown.heading(1, "Draft"), "# Draft\n");
+
+        let (open, close) = link_marks("https://example.com/a b>\nc");
+        assert_eq!(
+            Markdown.paragraph(format!("{}link{}", open, close)),
+            "[link](<https://example.com/a%20b%3E%0Ac>)\n"
+        );
+        let (open, close) = link_marks("scrivlnk://2E8A56B1");
+        assert_eq!(
+            Markdown.paragraph(format!("{}internal{}", open, close)),
+            "internal\n"
+        );
+        assert_eq!(strip_marks(marked(&[(italic, "plain")])), "plain");
+    }
+
+    #[test]
+    pub fn test_html() {
+        let bold = Format {
+            bold: true,
+            ..Default::default()
+        };
+        let (open, close) = link_marks("https://example.com/?a=1&b=2");
+        let line = format!(
+            "{}Fish & {}chips{} at {}the pub{}.{}check this{}",
+            Format::default().mark(),
+            bold.mark(),
+            Format::default().mark(),
+            open,
+            close,
+            ANNOTATION_OPEN,
+            ANNOTATION_CLOSE
+        );
+        assert_eq!(
+            Html.paragraph(line.clone()),
+            "<p>Fish &amp; <strong>chips</strong> at <a href=\"https://example.com/?a=1&amp;b=2\">the pub</a>.</p>\n<aside>check this</aside>"
+        );
+        assert_eq!(strip_marks(line), "Fish & chips at the pub.check this");
+
+        assert_eq!(
+            Html.paragraph("<$Scr_H::1>Title".to_string()),
+            "<h1>Title</h1>"
+        );
+        assert_eq!(
+            Html.paragraph(format!("{}note{}", ANNOTATION_OPEN, ANNOTATION_CLOSE)),
+            "<aside>note</aside>"
+        );
+
+        let (open, close) = link_marks("javascript:alert(1)");
+        assert_eq!(
+            Html.paragraph(format!("{}click{}", open, close)),
+            "<p>click</p>"
+        );
+    }
+}
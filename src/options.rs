@@ -1,9 +1,39 @@
 //! Scry command line options
-use crate::extract::{ContentSpec, FolderSpec};
+use crate::expr::Expression;
+use crate::extract::{ContentSpec, FolderSpec, ItemFilter, OutputFormat, Selection};
+use crate::glob::BinderPattern;
+use crate::scrivx::{parse_item_reference, parse_time_bound};
+use crate::tree::TreeOptions;
+use chrono::{DateTime, FixedOffset};
 use std::collections::HashSet;
 use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
 use structopt::StructOpt;
+use uuid::Uuid;
+
+/// Parse a FIELD=VALUE pair
+fn parse_field_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((field, value)) => Ok((field.trim().to_string(), value.trim().to_string())),
+        None => Err(format!("expected FIELD=VALUE but found {:?}", s)),
+    }
+}
+
+/// Parse an output format name
+fn parse_format(s: &str) -> Result<OutputFormat, String> {
+    match s {
+        "text" => Ok(OutputFormat::Text),
+        "markdown" => Ok(OutputFormat::Markdown),
+        "html" => Ok(OutputFormat::Html),
+        _ => Err(format!("unknown output format {:?}", s)),
+    }
+}
+
+/// Parse an item reference (UUID or x-scrivener-item link)
+fn parse_item(s: &str) -> Result<Uuid, String> {
+    parse_item_reference(s)
+        .ok_or_else(|| format!("expected UUID or x-scrivener-item link but found {:?}", s))
+}
 
 #[derive(Debug, StructOpt)]
 #[structopt(name = "scry", about = "Extract content from scrivener project")]
@@ -28,6 +58,64 @@ pub struct Opt {
     #[structopt(short = "F", long = "folder")]
     folders: Vec<String>,
 
+    /// Include items whose binder path matches a glob, e.g.
+    /// "Draft/Part Two/**" or "**/Ch*/*" (`**` matches any depth)
+    #[structopt(short = "P", long = "path")]
+    paths: Vec<BinderPattern>,
+
+    /// Include the items of a collection (in collection order) instead
+    /// of folders
+    #[structopt(long = "collection")]
+    collection: Option<String>,
+
+    /// Include a specific item instead of folders, given by UUID or
+    /// x-scrivener-item link (Scrivener 3 projects only)
+    #[structopt(
+        long = "item",
+        conflicts_with_all = &["collection", "draft", "research", "trash", "all", "folders", "paths"],
+        parse(try_from_str = parse_item)
+    )]
+    items: Vec<Uuid>,
+
+    /// Also include the descendants of items given by --item
+    #[structopt(long, requires = "items")]
+    descendants: bool,
+
+    /// Only include items that would be compiled, skipping items
+    /// (and their subtrees) excluded from compile
+    #[structopt(long)]
+    compile_only: bool,
+
+    /// Only include items with label
+    #[structopt(long = "label")]
+    labels: Vec<String>,
+
+    /// Only include items with status
+    #[structopt(long = "status")]
+    statuses: Vec<String>,
+
+    /// Only include items with keyword (or any of its child keywords)
+    #[structopt(long = "keyword")]
+    keywords: Vec<String>,
+
+    /// Only include items with custom metadata value (FIELD=VALUE)
+    #[structopt(long = "meta", parse(try_from_str = parse_field_value))]
+    metadata: Vec<(String, String)>,
+
+    /// Only include items modified at or after date / time (e.g.
+    /// 2022-07-01 or "2022-07-01 10:00:00 +0100")
+    #[structopt(long, parse(try_from_str = parse_time_bound))]
+    modified_since: Option<DateTime<FixedOffset>>,
+
+    /// Only include items modified before date / time
+    #[structopt(long, parse(try_from_str = parse_time_bound))]
+    modified_before: Option<DateTime<FixedOffset>>,
+
+    /// Only include items satisfying an expression, e.g.
+    /// 'type == Text and status != "Done" and title ~ /^Scene/'
+    #[structopt(long = "where")]
+    conditions: Vec<Expression>,
+
     /// Include paragraphs from item content
     #[structopt(short, long)]
     content: bool,
@@ -48,18 +136,81 @@ pub struct Opt {
     #[structopt(short = "m", long)]
     comments: bool,
 
+    /// Include inline footnotes from item content
+    #[structopt(short = "f", long)]
+    footnotes: bool,
+
+    /// Leave numbered markers (e.g. "[1]") in content in place of
+    /// inline footnotes instead of dropping them
+    #[structopt(long)]
+    footnote_markers: bool,
+
     /// Include synopses
     #[structopt(short = "s", long)]
     synopses: bool,
 
-    /// Project, either a .scrivx file or a project bundle folder
-    /// (containing a .scrivx file)
+    /// Project, either a .scrivx file, a project bundle folder
+    /// (containing a .scrivx file) or a zipped backup (.scriv.zip)
     #[structopt(name = "PROJECT")]
     project: PathBuf,
 
     /// Maintain item structure and UUIDs (not hierarchy)
     #[structopt(short = "I", long)]
     itemise: bool,
+
+    /// Nest itemised items in `children` arrays following the binder
+    #[structopt(long, requires = "itemise")]
+    nested: bool,
+
+    /// List the binder outline of the selected items instead of
+    /// extracting content
+    #[structopt(short = "T", long, conflicts_with = "itemise")]
+    tree: bool,
+
+    /// Extra details to show in the tree listing
+    #[structopt(
+        long,
+        requires = "tree",
+        use_delimiter = true,
+        possible_values = &["uuid", "label", "status", "words"]
+    )]
+    show: Vec<String>,
+
+    /// Limit the tree listing to this many levels below the
+    /// shallowest selected item
+    #[structopt(long, requires = "tree")]
+    max_depth: Option<usize>,
+
+    /// Output syntax for extracted text: plain text, or markdown or
+    /// html preserving bold, italic, strikethrough, underline,
+    /// headings and links
+    #[structopt(
+        long,
+        conflicts_with_all = &["itemise", "tree"],
+        possible_values = &["text", "markdown", "html"],
+        parse(try_from_str = parse_format)
+    )]
+    format: Option<OutputFormat>,
+
+    /// Wrap html output in a complete document, each item in a
+    /// section headed by its title (only with --format html)
+    #[structopt(long, requires = "format")]
+    standalone: bool,
+
+    /// Prefix each line with the binder path of its item
+    /// (e.g. "Draft / Part 2 / Scene 3") and a tab; the document and
+    /// section lines added by --standalone are not prefixed
+    #[structopt(long)]
+    prefix_paths: bool,
+
+    /// Prefix item titles with their outline number (e.g. "2.3.1")
+    /// relative to the selected root folder
+    #[structopt(long)]
+    number_headings: bool,
+
+    /// Proceed without warning even if the project is open in Scrivener
+    #[structopt(long)]
+    force: bool,
 }
 
 impl Opt {
@@ -71,6 +222,50 @@ impl Opt {
         self.itemise
     }
 
+    pub fn nested(&self) -> bool {
+        self.nested
+    }
+
+    pub fn tree(&self) -> bool {
+        self.tree
+    }
+
+    /// What to show in a tree listing
+    pub fn tree_options(&self) -> TreeOptions {
+        let show = |s: &str| self.show.iter().any(|x| x == s);
+        TreeOptions {
+            uuids: show("uuid"),
+            labels: show("label"),
+            statuses: show("status"),
+            word_counts: show("words"),
+            max_depth: self.max_depth,
+        }
+    }
+
+    pub fn prefix_paths(&self) -> bool {
+        self.prefix_paths
+    }
+
+    pub fn number_headings(&self) -> bool {
+        self.number_headings
+    }
+
+    pub fn footnote_markers(&self) -> bool {
+        self.footnote_markers
+    }
+
+    pub fn format(&self) -> OutputFormat {
+        self.format.unwrap_or_default()
+    }
+
+    pub fn standalone(&self) -> bool {
+        self.standalone
+    }
+
+    pub fn force(&self) -> bool {
+        self.force
+    }
+
     /// Return the folders to include in the output
     pub fn folder_specs(&self) -> HashSet<FolderSpec> {
         let mut folder_specs = HashSet::new();
@@ -86,6 +281,9 @@ impl Opt {
         for s in &self.folders {
             folder_specs.insert(FolderSpec::NamedFolder(s.to_string()));
         }
+        for pattern in &self.paths {
+            folder_specs.insert(FolderSpec::Path(pattern.clone()));
+        }
         if self.all {
             folder_specs.insert(FolderSpec::Any);
         }
@@ -95,6 +293,31 @@ impl Opt {
         folder_specs
     }
 
+    /// The items to include in the output
+    pub fn selection(&self) -> Selection {
+        Selection {
+            folder_specs: self.folder_specs(),
+            collection: self.collection.clone(),
+            items: self.items.clone(),
+            descendants: self.descendants,
+            compile_only: self.compile_only,
+            item_filter: self.item_filter(),
+        }
+    }
+
+    /// Criteria that included items must satisfy
+    pub fn item_filter(&self) -> ItemFilter {
+        ItemFilter {
+            labels: self.labels.clone(),
+            statuses: self.statuses.clone(),
+            keywords: self.keywords.clone(),
+            metadata: self.metadata.clone(),
+            modified_since: self.modified_since,
+            modified_before: self.modified_before,
+            conditions: self.conditions.clone(),
+        }
+    }
+
     /// The types of content to extract for each item
     pub fn content_specs(&self) -> HashSet<ContentSpec> {
         let mut content_specs = HashSet::new();
@@ -116,6 +339,9 @@ impl Opt {
         if self.comments {
             content_specs.insert(ContentSpec::Comments);
         }
+        if self.footnotes {
+            content_specs.insert(ContentSpec::Footnotes);
+        }
         if content_specs.is_empty() {
             content_specs.insert(ContentSpec::Content);
         }
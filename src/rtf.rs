@@ -2,6 +2,7 @@
 //!
 //! This is heavily based on / stolen from https://github.com/compenguy/rtf2text
 use crate::error::Result;
+use crate::markup::{self, Format};
 use lazy_static::lazy_static;
 use rtf_grimoire::tokenizer::{parse, Token};
 use std::cell::RefCell;
@@ -10,6 +11,7 @@ use std::collections::VecDeque;
 use std::fs;
 use std::path::Path;
 use std::rc::Rc;
+use uuid::Uuid;
 
 /// An iterator over paragraphs in an RTF file
 pub type ParagraphIterator = Snipperator<std::vec::IntoIter<Token>>;
@@ -27,6 +29,68 @@ pub fn parse_rtf(data: &[u8]) -> Result<ParagraphIterator> {
     Ok(Snipperator::new(tokens.into_iter()))
 }
 
+/// Parse a buffer containing rtf bytes and return an iterator over
+/// lines of text carrying character formatting marks (see
+/// `markup`)
+pub fn parse_rtf_marked(data: &[u8]) -> Result<ParagraphIterator> {
+    let mut paragraphs = parse_rtf(data)?;
+    paragraphs.set_marks(true);
+    Ok(paragraphs)
+}
+
+/// Parse a buffer containing rtf bytes and return an iterator over
+/// lines of text carrying marks around each link anchor (see
+/// `markup::split_anchors`)
+pub fn parse_rtf_anchored(data: &[u8]) -> Result<ParagraphIterator> {
+    let mut paragraphs = parse_rtf(data)?;
+    paragraphs.set_anchor_marks(true);
+    Ok(paragraphs)
+}
+
+/// Parse a buffer containing rtf bytes and return all the link
+/// anchors it contains
+pub fn parse_rtf_anchors(data: &[u8]) -> Result<Vec<Anchor>> {
+    let mut paragraphs = parse_rtf(data)?;
+    paragraphs.by_ref().for_each(drop);
+    Ok(paragraphs.anchors())
+}
+
+/// Link prefix used by Scrivener for linked comments
+const COMMENT_LINK_PREFIX: &str = "scrivcmt://";
+
+/// A span of paragraph text that is the result of a HYPERLINK field
+///
+/// Scrivener anchors linked comments this way, using links of the
+/// form `scrivcmt://<UUID>`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Anchor {
+    /// Target of the link
+    pub link: String,
+    /// Index of the RTF paragraph containing the span
+    pub paragraph: usize,
+    /// Character offset of the start of the span within the paragraph
+    pub start: usize,
+    /// Character offset of the end of the span within the paragraph
+    pub end: usize,
+    /// The anchored text
+    pub text: String,
+}
+
+impl Anchor {
+    /// The ID of the linked comment if this anchors a comment
+    pub fn comment_id(&self) -> Option<Uuid> {
+        self.link
+            .strip_prefix(COMMENT_LINK_PREFIX)
+            .and_then(|id| Uuid::parse_str(id).ok())
+    }
+}
+
+/// Extract the link target from a HYPERLINK field instruction
+fn hyperlink_target(instruction: &str) -> Option<String> {
+    let rest = instruction.trim().strip_prefix("HYPERLINK")?.trim();
+    Some(rest.trim_matches('"').to_string())
+}
+
 /// A Snipperator is a filter that converts tokens into text snippets
 pub struct Snipperator<T>
 where
@@ -50,6 +114,24 @@ impl<T: Iterator<Item = Token>> Snipperator<T> {
             rtf_queue,
         }
     }
+
+    /// Link anchors encountered so far
+    ///
+    /// Complete only once the iterator is exhausted.
+    pub fn anchors(&self) -> Vec<Anchor> {
+        self.rtf_queue.borrow().anchors().to_vec()
+    }
+
+    /// Embed marks in the text where character formatting changes
+    pub fn set_marks(&mut self, marks: bool) {
+        self.rtf_queue.borrow_mut().marks = marks;
+    }
+
+    /// Embed marks in the text around each link anchor, identifying
+    /// it by its index in `anchors`
+    pub fn set_anchor_marks(&mut self, anchor_marks: bool) {
+        self.rtf_queue.borrow_mut().anchor_marks = anchor_marks;
+    }
 }
 
 impl<T: Iterator<Item = Token>> Iterator for Snipperator<T> {
@@ -108,6 +190,21 @@ pub trait DestinationArray {
         name: &str,
         encoding: Option<&'static encoding_rs::Encoding>,
     ) -> Option<String>;
+    /// Read text from destination if available and discard the destination
+    fn take_text(
+        &mut self,
+        name: &str,
+        encoding: Option<&'static encoding_rs::Encoding>,
+    ) -> Option<String>;
+    /// Write field result text to destination, noting the link that
+    /// it anchors
+    fn write_link(&mut self, name: &str, text: &str, _link: &str) {
+        self.write(name, text.as_bytes(), Some(encoding_rs::UTF_8));
+    }
+
+    /// Note the character formatting of text about to be written to
+    /// destination
+    fn set_format(&mut self, _name: &str, _format: Format) {}
 }
 
 /// A destination array that stores and writes to Destinations
@@ -171,6 +268,17 @@ impl DestinationArray for BasicDestinationArray {
         })
     }
 
+    /// Read text from named destination and remove it
+    fn take_text(
+        &mut self,
+        name: &str,
+        encoding: Option<&'static encoding_rs::Encoding>,
+    ) -> Option<String> {
+        let text = self.read_text(name, encoding);
+        self.dests.remove(name);
+        text
+    }
+
     fn destinations(&self) -> Vec<String> {
         self.dests.keys().cloned().collect()
     }
@@ -182,6 +290,18 @@ pub struct RtfQueueDestinationArray {
     basic: BasicDestinationArray,
     queue: VecDeque<String>,
     current: String,
+    /// Number of lines queued so far
+    line_count: usize,
+    /// Link anchors recorded so far
+    anchors: Vec<Anchor>,
+    /// Whether to embed formatting marks in lines
+    marks: bool,
+    /// Whether to embed marks around link anchors in lines
+    anchor_marks: bool,
+    /// Formatting of the next text written
+    format: Format,
+    /// Formatting in effect at the end of the current line
+    current_format: Format,
 }
 
 impl RtfQueueDestinationArray {
@@ -191,9 +311,29 @@ impl RtfQueueDestinationArray {
             basic,
             queue: VecDeque::new(),
             current: String::new(),
+            line_count: 0,
+            anchors: Vec::new(),
+            marks: false,
+            anchor_marks: false,
+            format: Format::default(),
+            current_format: Format::default(),
         }
     }
 
+    /// Push a formatting mark onto the current line if the format
+    /// has changed
+    fn mark_format(&mut self) {
+        if self.marks && self.format != self.current_format {
+            self.current.push(self.format.mark());
+            self.current_format = self.format;
+        }
+    }
+
+    /// Link anchors recorded so far
+    pub fn anchors(&self) -> &[Anchor] {
+        &self.anchors
+    }
+
     /// Pop a line from the front of the queue
     pub fn pop(&mut self) -> Option<String> {
         self.queue.pop_front()
@@ -241,7 +381,10 @@ impl DestinationArray for RtfQueueDestinationArray {
                 let text = &decoder.decode(bytes).0;
                 if text == "\n" {
                     self.queue.push_back(self.current.split_off(0));
+                    self.line_count += 1;
+                    self.current_format = Format::default();
                 } else {
+                    self.mark_format();
                     self.current.push_str(text);
                 }
             } else {
@@ -259,6 +402,57 @@ impl DestinationArray for RtfQueueDestinationArray {
     ) -> Option<String> {
         self.basic.read_text(name, encoding)
     }
+
+    fn take_text(
+        &mut self,
+        name: &str,
+        encoding: Option<&'static encoding_rs::Encoding>,
+    ) -> Option<String> {
+        self.basic.take_text(name, encoding)
+    }
+
+    /// Write field result text, recording an anchor if it lands in
+    /// the "rtf" destination
+    fn write_link(&mut self, name: &str, text: &str, link: &str) {
+        if name == "rtf" {
+            self.mark_format();
+            // comment links are not hyperlinks as far as the reader
+            // is concerned
+            let marks = Some(markup::link_marks(link))
+                .filter(|_| self.marks && !link.starts_with(COMMENT_LINK_PREFIX));
+            if let Some((ref open, _)) = marks {
+                self.current.push_str(open);
+            }
+            let anchor_marks =
+                Some(markup::anchor_marks(self.anchors.len())).filter(|_| self.anchor_marks);
+            if let Some((ref open, _)) = anchor_marks {
+                self.current.push_str(open);
+            }
+            let start = self.current.chars().count();
+            self.current.push_str(text);
+            self.anchors.push(Anchor {
+                link: link.to_string(),
+                paragraph: self.line_count,
+                start,
+                end: start + text.chars().count(),
+                text: text.to_string(),
+            });
+            if let Some((_, close)) = anchor_marks {
+                self.current.push(close);
+            }
+            if let Some((_, close)) = marks {
+                self.current.push(close);
+            }
+        } else {
+            self.write(name, text.as_bytes(), Some(encoding_rs::UTF_8));
+        }
+    }
+
+    fn set_format(&mut self, name: &str, format: Format) {
+        if name == "rtf" {
+            self.format = format;
+        }
+    }
 }
 
 /// The engine which is fed tokens and polled for snippets
@@ -340,14 +534,22 @@ impl SnippetEngine {
 
     /// Close top group
     fn close_group(&mut self) {
-        // if a field result destination has been populated, we pass
-        // that text to the parent group
+        // when a field closes, if its result destination has been
+        // populated, we pass that text to the parent group noting any
+        // hyperlink from the field instruction
         if let Some(top) = self.group_stack.pop() {
-            if let Some(text) = top.read_text("fldrslt") {
-                if let Some(enc) = top.current_encoding {
-                    self.write(&enc.encode(text.as_str()).0);
-                } else {
-                    self.write(text.as_bytes());
+            if top.current_destination() == Some("field") {
+                let instruction = top.take_text("fldinst");
+                if let Some(text) = top.take_text("fldrslt") {
+                    if let Some(parent) = self.group_stack.last_mut() {
+                        match instruction.as_deref().and_then(hyperlink_target) {
+                            Some(link) => parent.write_link(&text, &link),
+                            None => match top.current_encoding {
+                                Some(enc) => parent.write(&enc.encode(&text).0, None),
+                                None => parent.write(text.as_bytes(), None),
+                            },
+                        }
+                    }
                 }
             }
         }
@@ -452,6 +654,23 @@ impl Group {
         old
     }
 
+    /// Returns true if a toggle (e.g. `\b`) is on
+    fn toggled(&self, name: &str) -> bool {
+        self.values
+            .get(name)
+            .is_some_and(|arg| arg.is_none_or(|n| n != 0))
+    }
+
+    /// Current character formatting
+    pub fn format(&self) -> Format {
+        Format {
+            bold: self.toggled("b"),
+            italic: self.toggled("i"),
+            strike: self.toggled("strike"),
+            underline: self.toggled("ul"),
+        }
+    }
+
     /// Write the provided bytes to the current destination
     /// using the current encoding (or override) if required by the
     /// destinationl
@@ -461,6 +680,7 @@ impl Group {
         override_encoding: Option<&'static encoding_rs::Encoding>,
     ) {
         if let Some(dest) = self.current_destination() {
+            self.array.borrow_mut().set_format(dest, self.format());
             self.array.borrow_mut().write(
                 dest,
                 bytes,
@@ -469,9 +689,21 @@ impl Group {
         }
     }
 
-    /// Read text from named destination if posible
-    fn read_text(&self, name: &str) -> Option<String> {
-        self.array.borrow().read_text(name, self.current_encoding)
+    /// Write field result text to the current destination noting
+    /// the link that anchors it
+    pub fn write_link(&mut self, text: &str, link: &str) {
+        if let Some(dest) = self.current_destination() {
+            self.array.borrow_mut().set_format(dest, self.format());
+            self.array.borrow_mut().write_link(dest, text, link);
+        }
+    }
+
+    /// Read text from named destination if posible and discard the
+    /// destination
+    fn take_text(&self, name: &str) -> Option<String> {
+        self.array
+            .borrow_mut()
+            .take_text(name, self.current_encoding)
     }
 }
 
@@ -1378,7 +1610,7 @@ lazy_static! {
     m.insert("pindtabqc", Box::new(control_value_set_state_default));
     m.insert("pindtabql", Box::new(control_value_set_state_default));
     m.insert("pindtabqr", Box::new(control_value_set_state_default));
-    m.insert("plain", Box::new(control_value_set_state_default));
+    m.insert("plain", Box::new(control_flag_reset_format));
     m.insert("pmartabqc", Box::new(control_value_set_state_default));
     m.insert("pmartabql", Box::new(control_value_set_state_default));
     m.insert("pmartabqr", Box::new(control_value_set_state_default));
@@ -1716,7 +1948,7 @@ lazy_static! {
     m.insert("txbxtwlast", Box::new(control_value_set_state_default));
     m.insert("txbxtwno", Box::new(control_value_set_state_default));
     m.insert("uld", Box::new(control_value_set_state_default));
-    m.insert("ulnone", Box::new(control_value_set_state_default));
+    m.insert("ulnone", Box::new(control_flag_reset_format));
     m.insert("ulw", Box::new(control_value_set_state_default));
     m.insert("useltbaln", Box::new(control_value_set_state_default));
     m.insert("usenormstyforlist", Box::new(control_value_set_state_default));
@@ -2442,6 +2674,19 @@ fn control_flag_set_state_encoding(state: &mut Group, name: &str, arg: Option<i3
     state.set_value(name, arg);
 }
 
+/// Handle `\plain` and `\ulnone` by switching off the character
+/// formatting toggles they reset
+fn control_flag_reset_format(state: &mut Group, name: &str, arg: Option<i32>) {
+    let toggles: &[&str] = match name {
+        "ulnone" => &["ul"],
+        _ => &["b", "i", "strike", "ul"],
+    };
+    for toggle in toggles {
+        state.set_value(toggle, Some(0));
+    }
+    state.set_value(name, arg);
+}
+
 fn control_value_set_state_default(state: &mut Group, name: &str, arg: Option<i32>) {
     state.set_value(name, arg);
 }
@@ -2556,7 +2801,78 @@ pub mod test {
 
 \f0\fs26 \cf0 This is commented-on {\field{\*\fldinst{HYPERLINK "scrivcmt://3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6"}}{\fldrslt text}}.\
 }"#.as_bytes();
-        let lines: Vec<String> = parse_rtf(source).unwrap().collect();
+        let mut paragraphs = parse_rtf(source).unwrap();
+        let lines: Vec<String> = paragraphs.by_ref().collect();
         assert_eq!(lines, vec!["This is commented-on text."]);
+
+        let anchors = paragraphs.anchors();
+        assert_eq!(anchors.len(), 1);
+        assert_eq!(
+            anchors[0].comment_id(),
+            Some(Uuid::parse_str("3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6").unwrap())
+        );
+        assert_eq!(anchors[0].paragraph, 0);
+        assert_eq!((anchors[0].start, anchors[0].end), (21, 25));
+        assert_eq!(anchors[0].text, "text");
+    }
+
+    #[test]
+    pub fn test_field_result_encoding() {
+        let source = r#"{\rtf1\ansi\ansicpg1252
+\pard Page {\field{\*\fldinst{PAGE}}{\fldrslt caf\'e9}}.\
+}"#
+        .as_bytes();
+        let lines: Vec<String> = parse_rtf(source).unwrap().collect();
+        assert_eq!(lines, vec!["Page café."]);
+    }
+
+    #[test]
+    pub fn test_field_result_written_once() {
+        let source = r#"{\rtf1\ansi\ansicpg1252
+\pard First.\
+Second {\field{\*\fldinst{HYPERLINK "scrivcmt://3320CF04-2AE2-4D08-A1A4-3A5CFB9F43A6"}}{\fldrslt link}} and {\b bold}.\
+}"#
+        .as_bytes();
+        let mut paragraphs = parse_rtf(source).unwrap();
+        let lines: Vec<String> = paragraphs.by_ref().collect();
+        assert_eq!(lines, vec!["First.", "Second link and bold."]);
+
+        let anchors = paragraphs.anchors();
+        assert_eq!(anchors.len(), 1);
+        assert_eq!(anchors[0].paragraph, 1);
+        assert_eq!((anchors[0].start, anchors[0].end), (7, 11));
+    }
+
+    #[test]
+    pub fn test_formatting_marks() {
+        let source = r#"{\rtf1\ansi\ansicpg1252
+\pard Some {\i italic} and \b bold\b0  or \ul\strike both\plain  text.\
+}"#
+        .as_bytes();
+        let lines: Vec<String> = parse_rtf_marked(source).unwrap().collect();
+        let mark = |bold, italic, strike, underline| {
+            Format {
+                bold,
+                italic,
+                strike,
+                underline,
+            }
+            .mark()
+        };
+        assert_eq!(
+            lines,
+            vec![format!(
+                "Some {}italic{} and {}bold{} or {}both{} text.",
+                mark(false, true, false, false),
+                mark(false, false, false, false),
+                mark(true, false, false, false),
+                mark(false, false, false, false),
+                mark(false, false, true, true),
+                mark(false, false, false, false),
+            )]
+        );
+
+        let lines: Vec<String> = parse_rtf(source).unwrap().collect();
+        assert_eq!(lines, vec!["Some italic and bold or both text."]);
     }
 }
@@ -1,9 +1,13 @@
 //! Representation and parsing of .scrivx project files
+use crate::error::ScryError;
+use crate::xml::{Document, Element, Node};
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, TimeZone};
 use quick_xml::de::{from_reader, DeError};
 use serde::{Deserialize, Deserializer};
 use std::{
     fmt,
-    io::{BufReader, Read},
+    io::{BufReader, Read, Write},
+    str::FromStr,
 };
 use uuid::Uuid;
 
@@ -22,22 +26,94 @@ pub struct ScrivenerProject {
     pub author: String,
     #[serde(rename = "Binder")]
     pub binder: Binder,
-    #[serde(rename = "ModID")]
+    #[serde(rename = "ModID", default)]
     pub mod_id: Uuid,
+    #[serde(rename = "LabelSettings", default)]
+    pub label_settings: LabelSettings,
+    #[serde(rename = "StatusSettings", default)]
+    pub status_settings: StatusSettings,
+    #[serde(rename = "Keywords", default)]
+    pub keywords: Keywords,
+    #[serde(rename = "CustomMetaDataSettings", default)]
+    pub custom_metadata_settings: CustomMetaDataSettings,
+    #[serde(rename = "Collections", default)]
+    pub collections: Collections,
 }
 
 impl ScrivenerProject {
     /// Parse a scrivx project file
     pub fn parse<T: Read>(input: T) -> Result<Self, DeError> {
         let r = BufReader::new(input);
-        from_reader(r)
+        let mut project: ScrivenerProject = from_reader(r)?;
+        project.resolve();
+        Ok(project)
+    }
+
+    /// Resolve binder item references to project-level definitions
+    fn resolve(&mut self) {
+        let labels = &self.label_settings;
+        let statuses = &self.status_settings;
+        let keywords = &self.keywords;
+        let fields = &self.custom_metadata_settings;
+        for item in self.binder.binder_items.iter_mut() {
+            item.walk_mut(&mut |item| {
+                item.label = labels.label(item.metadata.label_id).cloned();
+                item.status = statuses.status(item.metadata.status_id).cloned();
+                item.keywords = item
+                    .keyword_refs
+                    .ids
+                    .iter()
+                    .filter_map(|id| keywords.keyword(*id))
+                    .map(|k| k.title.clone())
+                    .collect();
+                item.custom_metadata = fields.resolve(&item.metadata.custom_metadata);
+            });
+        }
+    }
+
+    /// The label definitions of the project
+    pub fn labels(&self) -> &[Label] {
+        &self.label_settings.labels.labels
+    }
+
+    /// The status definitions of the project
+    pub fn statuses(&self) -> &[Status] {
+        &self.status_settings.status_items.statuses
+    }
+
+    /// The top level keywords of the project's keyword hierarchy
+    pub fn keywords(&self) -> &[Keyword] {
+        &self.keywords.keywords
+    }
+
+    /// The custom metadata fields defined in the project
+    pub fn custom_metadata_fields(&self) -> &[MetaDataField] {
+        &self.custom_metadata_settings.fields
     }
 
     /// An iterator over all items in the project's binder
-    pub fn iter(&self) -> BinderIterator {
+    pub fn iter(&self) -> BinderIterator<'_> {
         BinderIterator::new(self.binder.binder_items.iter().collect())
     }
 
+    /// An iterator over all items in the project's binder with their
+    /// positions
+    pub fn entries(&self) -> BinderPathIterator<'_> {
+        BinderPathIterator::new(
+            self.binder
+                .binder_items
+                .iter()
+                .enumerate()
+                .map(|(index, item)| BinderEntry::root(item, index))
+                .collect(),
+        )
+    }
+
+    /// The file layout implied by the project version
+    pub fn format(&self) -> ProjectFormat {
+        ProjectFormat::of_version(&self.version)
+    }
+
     /// Find the draft folder
     pub fn draft(&self) -> &BinderItem {
         for i in self.iter() {
@@ -47,10 +123,334 @@ impl ScrivenerProject {
         }
         panic!("No draft folder in project!")
     }
+
+    /// Find a binder item by UUID
+    pub fn item(&self, uuid: &Uuid) -> Option<&BinderItem> {
+        self.iter().find(|i| &i.uuid == uuid)
+    }
+
+    /// The project's collections
+    pub fn collections(&self) -> &[Collection] {
+        &self.collections.collections
+    }
+
+    /// Find a collection by title
+    pub fn collection(&self, title: &str) -> Option<&Collection> {
+        self.collections().iter().find(|c| c.title == title)
+    }
+}
+
+/// A .scrivx file held losslessly for modification
+///
+/// Unlike [`ScrivenerProject`], which only models what scry reads,
+/// this retains every element, attribute and comment of the original
+/// so that it can be written back without losing anything Scrivener
+/// (or a later version of it) depends on.
+///
+/// Binder changes are made through methods which edit the underlying
+/// XML in place, leaving everything they don't touch as it was.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProjectDocument {
+    document: Document,
+    /// Items created since the document was parsed
+    created: Vec<Uuid>,
+}
+
+impl ProjectDocument {
+    /// Parse a scrivx project file
+    pub fn parse<T: Read>(input: T) -> crate::error::Result<Self> {
+        let document = Document::parse(BufReader::new(input))?;
+        Ok(ProjectDocument {
+            document,
+            created: vec![],
+        })
+    }
+
+    /// Write the project file
+    pub fn write<W: Write>(&self, output: W) -> crate::error::Result<()> {
+        Ok(self.document.write(output)?)
+    }
+
+    /// Serialise the project file to bytes
+    pub fn to_bytes(&self) -> crate::error::Result<Vec<u8>> {
+        Ok(self.document.to_bytes()?)
+    }
+
+    /// The typed project model of the document in its current state
+    pub fn project(&self) -> crate::error::Result<ScrivenerProject> {
+        Ok(ScrivenerProject::parse(&self.to_bytes()?[..])?)
+    }
+
+    /// The `ScrivenerProject` root element
+    pub fn root(&self) -> &Element {
+        &self.document.root
+    }
+
+    /// The `ScrivenerProject` root element (mutable)
+    pub fn root_mut(&mut self) -> &mut Element {
+        &mut self.document.root
+    }
+
+    /// The file layout implied by the project version
+    pub fn format(&self) -> ProjectFormat {
+        ProjectFormat::of_version(self.root().attribute("Version").unwrap_or_default())
+    }
+
+    /// Items created since the document was parsed (or last saved),
+    /// which will need folders in the bundle
+    pub fn created_items(&self) -> &[Uuid] {
+        &self.created
+    }
+
+    /// Forget the items created, once their folders exist
+    pub fn clear_created_items(&mut self) {
+        self.created.clear();
+    }
+
+    /// Create a new, empty text item at the end of `parent`'s children
+    /// and return its UUID
+    ///
+    /// Not supported in legacy projects, whose items are identified by
+    /// integer IDs with content files in a shared folder.
+    pub fn create_text_item(&mut self, parent: &Uuid, title: &str) -> crate::error::Result<Uuid> {
+        if self.format() == ProjectFormat::Legacy {
+            return Err(ScryError::UnsupportedInLegacy("creating items"));
+        }
+
+        let uuid = Uuid::new_v4();
+        let now = timestamp_now();
+
+        let mut item = Element::new("BinderItem");
+        item.set_attribute("UUID", uuid_attribute(&uuid));
+        item.set_attribute("Type", "Text");
+        item.set_attribute("Created", now.clone());
+        item.set_attribute("Modified", now);
+        item.child_or_insert("Title").set_text(title);
+        item.child_or_insert("MetaData")
+            .child_or_insert("IncludeInCompile")
+            .set_text("Yes");
+
+        self.item_mut(parent)?
+            .child_or_insert("Children")
+            .children
+            .push(Node::Element(item));
+        self.created.push(uuid);
+        self.mark_modified();
+        Ok(uuid)
+    }
+
+    /// Move an item (with its descendants) to the end of `parent`'s
+    /// children
+    pub fn move_item(&mut self, uuid: &Uuid, parent: &Uuid) -> crate::error::Result<()> {
+        let moved = self.item_mut(uuid)?;
+        if moved.attribute("UUID").and_then(parse_uuid) == Some(*parent)
+            || find_item(moved.child_mut("Children"), parent).is_some()
+        {
+            return Err(ScryError::InvalidMove(*uuid, *parent));
+        }
+        self.item_mut(parent)?;
+
+        let item = self
+            .root_mut()
+            .child_mut("Binder")
+            .and_then(|binder| take_item(binder, uuid))
+            .ok_or(ScryError::UnknownItem(*uuid))?;
+        self.item_mut(parent)?
+            .child_or_insert("Children")
+            .children
+            .push(Node::Element(item));
+        self.mark_modified();
+        Ok(())
+    }
+
+    /// Change the title of an item
+    pub fn rename_item(&mut self, uuid: &Uuid, title: &str) -> crate::error::Result<()> {
+        let item = self.item_mut(uuid)?;
+        item.child_or_insert("Title").set_text(title);
+        touch(item);
+        self.mark_modified();
+        Ok(())
+    }
+
+    /// Set whether an item is included in compile
+    pub fn set_include_in_compile(
+        &mut self,
+        uuid: &Uuid,
+        include: bool,
+    ) -> crate::error::Result<()> {
+        let item = self.item_mut(uuid)?;
+        item.child_or_insert("MetaData")
+            .child_or_insert("IncludeInCompile")
+            .set_text(if include { "Yes" } else { "No" });
+        touch(item);
+        self.mark_modified();
+        Ok(())
+    }
+
+    /// Set an item's label by name, or clear it with `None`
+    pub fn set_label(&mut self, uuid: &Uuid, label: Option<&str>) -> crate::error::Result<()> {
+        let id = match label {
+            Some(name) => self
+                .setting_id("LabelSettings", "Labels", name)
+                .ok_or_else(|| ScryError::UnknownLabel(name.to_string()))?,
+            None => no_id().to_string(),
+        };
+        self.set_metadata_value(uuid, "LabelID", id)
+    }
+
+    /// Set an item's status by name, or clear it with `None`
+    pub fn set_status(&mut self, uuid: &Uuid, status: Option<&str>) -> crate::error::Result<()> {
+        let id = match status {
+            Some(name) => self
+                .setting_id("StatusSettings", "StatusItems", name)
+                .ok_or_else(|| ScryError::UnknownStatus(name.to_string()))?,
+            None => no_id().to_string(),
+        };
+        self.set_metadata_value(uuid, "StatusID", id)
+    }
+
+    /// Set a value in an item's `MetaData` element
+    fn set_metadata_value(
+        &mut self,
+        uuid: &Uuid,
+        name: &str,
+        value: String,
+    ) -> crate::error::Result<()> {
+        let item = self.item_mut(uuid)?;
+        item.child_or_insert("MetaData")
+            .child_or_insert(name)
+            .set_text(value);
+        touch(item);
+        self.mark_modified();
+        Ok(())
+    }
+
+    /// ID of a label or status definition by name
+    fn setting_id(&self, settings: &str, list: &str, name: &str) -> Option<String> {
+        self.root()
+            .child(settings)?
+            .child(list)?
+            .elements()
+            .find(|e| e.text() == name)?
+            .attribute("ID")
+            .map(str::to_string)
+    }
+
+    /// The `BinderItem` element with the specified UUID
+    fn item_mut(&mut self, uuid: &Uuid) -> crate::error::Result<&mut Element> {
+        find_item(self.root_mut().child_mut("Binder"), uuid).ok_or(ScryError::UnknownItem(*uuid))
+    }
+
+    /// Record a change to the project
+    fn mark_modified(&mut self) {
+        let root = self.root_mut();
+        root.set_attribute("Modified", timestamp_now());
+        if root.attribute("ModID").is_some() {
+            root.set_attribute("ModID", uuid_attribute(&Uuid::new_v4()));
+        }
+    }
+}
+
+/// Find a binder item element among the items within `container`
+/// (a `Binder` or `Children` element) or their descendants
+fn find_item<'e>(container: Option<&'e mut Element>, uuid: &Uuid) -> Option<&'e mut Element> {
+    for item in container?.elements_mut() {
+        if item.attribute("UUID").and_then(parse_uuid) == Some(*uuid) {
+            return Some(item);
+        }
+        if let Some(found) = find_item(item.child_mut("Children"), uuid) {
+            return Some(found);
+        }
+    }
+    None
+}
+
+/// Remove a binder item element from within `container` or its
+/// descendants
+fn take_item(container: &mut Element, uuid: &Uuid) -> Option<Element> {
+    let position = container.children.iter().position(|node| match node {
+        Node::Element(e) => e.attribute("UUID").and_then(parse_uuid) == Some(*uuid),
+        _ => false,
+    });
+    if let Some(index) = position {
+        if let Node::Element(e) = container.children.remove(index) {
+            return Some(e);
+        }
+    }
+    container
+        .elements_mut()
+        .filter_map(|item| item.child_mut("Children"))
+        .find_map(|children| take_item(children, uuid))
+}
+
+/// Parse a UUID attribute value
+fn parse_uuid(s: &str) -> Option<Uuid> {
+    Uuid::parse_str(s).ok()
+}
+
+/// Parse a reference to a binder item, either a bare UUID or an
+/// `x-scrivener-item:///<project path>?id=<UUID>` link as copied
+/// from Scrivener
+pub fn parse_item_reference(s: &str) -> Option<Uuid> {
+    let s = s.trim();
+    match s.strip_prefix("x-scrivener-item:") {
+        Some(link) => {
+            let (_, query) = link.split_once('?')?;
+            query
+                .split('&')
+                .find_map(|param| param.strip_prefix("id="))
+                .and_then(parse_uuid)
+        }
+        None => parse_uuid(s),
+    }
+}
+
+/// Format a UUID as Scrivener does
+fn uuid_attribute(uuid: &Uuid) -> String {
+    uuid.as_hyphenated()
+        .encode_upper(&mut Uuid::encode_buffer())
+        .to_string()
+}
+
+/// The current time in Scrivener's timestamp format
+fn timestamp_now() -> String {
+    Local::now().format(TIMESTAMP_FORMAT).to_string()
+}
+
+/// Update an item's modification time
+fn touch(item: &mut Element) {
+    item.set_attribute("Modified", timestamp_now());
+}
+
+/// Layout of project files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProjectFormat {
+    /// Scrivener 3: item files under `Files/Data/<UUID>/`
+    Current,
+    /// Scrivener 2 / Windows 1.x: item files named by integer ID
+    /// under `Files/Docs/`
+    Legacy,
+}
+
+impl ProjectFormat {
+    /// The file layout implied by a project version
+    ///
+    /// Scrivener 3 projects are version 2.0 or later, Scrivener 2 and
+    /// Windows 1.x projects are earlier.
+    pub fn of_version(version: &str) -> Self {
+        match version
+            .split('.')
+            .next()
+            .and_then(|major| major.trim().parse::<u32>().ok())
+        {
+            Some(major) if major < 2 => ProjectFormat::Legacy,
+            _ => ProjectFormat::Current,
+        }
+    }
 }
 
 /// Binder item types
-#[derive(Debug, Deserialize, PartialEq)]
+#[derive(Debug, Deserialize, PartialEq, Default)]
 pub enum BinderItemType {
     /// The single draft folder
     DraftFolder,
@@ -69,6 +469,7 @@ pub enum BinderItemType {
     /// Archived web content
     WebArchive,
     /// Other content type
+    #[default]
     Other,
 }
 
@@ -88,14 +489,59 @@ impl fmt::Display for BinderItemType {
     }
 }
 
-impl Default for BinderItemType {
-    fn default() -> Self {
-        BinderItemType::Other
+/// An RGB colour as recorded by Scrivener
+///
+/// Scrivener stores colours as space separated red, green and blue
+/// components in the range 0.0 - 1.0, e.g. "0.698 0.847 0.976".
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Color {
+    pub red: f32,
+    pub green: f32,
+    pub blue: f32,
+}
+
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let components: Vec<f32> = s
+            .split_whitespace()
+            .map(|c| c.parse::<f32>())
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("invalid colour {:?}: {}", s, e))?;
+        match components[..] {
+            [red, green, blue, ..] => Ok(Color { red, green, blue }),
+            _ => Err(format!("invalid colour {:?}", s)),
+        }
+    }
+}
+
+/// Colours display as hex RGB, e.g. "#B2D8F9"
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let byte = |c: f32| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+        write!(
+            f,
+            "#{:02X}{:02X}{:02X}",
+            byte(self.red),
+            byte(self.green),
+            byte(self.blue)
+        )
+    }
+}
+
+impl<'de> Deserialize<'de> for Color {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
     }
 }
 
 /// Deserialise a boolean from Yes / No
-fn de_from_yes_no<'de, D>(deserializer: D) -> Result<bool, D::Error>
+pub(crate) fn de_from_yes_no<'de, D>(deserializer: D) -> Result<bool, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -103,12 +549,17 @@ where
     Ok(s == "Yes")
 }
 
+/// The ID Scrivener uses for "No Label" / "No Status"
+fn no_id() -> i32 {
+    -1
+}
+
 /// Binder item metadata
 #[derive(Debug, Deserialize, PartialEq)]
 pub struct BinderItemMetadata {
-    #[serde(rename = "LabelID", default)]
+    #[serde(rename = "LabelID", default = "no_id")]
     pub label_id: i32,
-    #[serde(rename = "StatusID", default)]
+    #[serde(rename = "StatusID", default = "no_id")]
     pub status_id: i32,
     #[serde(
         rename = "IncludeInCompile",
@@ -116,6 +567,431 @@ pub struct BinderItemMetadata {
         default
     )]
     pub include_in_compile: bool,
+    #[serde(rename = "CustomMetaData", default)]
+    pub custom_metadata: CustomMetaData,
+}
+
+impl Default for BinderItemMetadata {
+    fn default() -> Self {
+        BinderItemMetadata {
+            label_id: no_id(),
+            status_id: no_id(),
+            include_in_compile: false,
+            custom_metadata: CustomMetaData::default(),
+        }
+    }
+}
+
+/// Format of timestamps in project files
+const TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S %z";
+
+/// Parse a timestamp in Scrivener's format, e.g. "2022-07-01 10:00:00 +0100"
+pub fn parse_timestamp(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(s.trim(), TIMESTAMP_FORMAT).ok()
+}
+
+/// Parse a time bound from a Scrivener or RFC 3339 timestamp or a
+/// date (taken as the start of the day, local time)
+pub fn parse_time_bound(s: &str) -> Result<DateTime<FixedOffset>, String> {
+    if let Some(time) = parse_timestamp(s) {
+        return Ok(time);
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(s) {
+        return Ok(time);
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|date| date.and_hms_opt(0, 0, 0))
+        .and_then(|time| Local.from_local_datetime(&time).earliest())
+        .map(|time| time.fixed_offset())
+        .ok_or_else(|| format!("expected date (YYYY-MM-DD) or timestamp but found {:?}", s))
+}
+
+/// Deserialise an optional timestamp in Scrivener's format, treating
+/// one that cannot be parsed as missing
+fn de_from_timestamp<'de, D>(deserializer: D) -> Result<Option<DateTime<FixedOffset>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let s = String::deserialize(deserializer)?;
+    Ok(parse_timestamp(&s))
+}
+
+/// A binder item's raw custom metadata values
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct CustomMetaData {
+    #[serde(rename = "MetaDataItem", default)]
+    pub items: Vec<MetaDataItem>,
+}
+
+/// A raw custom metadata value, referring to a field by ID
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct MetaDataItem {
+    #[serde(rename = "FieldID")]
+    pub field_id: String,
+    #[serde(rename = "Value", default)]
+    pub value: String,
+}
+
+/// The project's custom metadata field definitions
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct CustomMetaDataSettings {
+    #[serde(rename = "MetaDataField", default)]
+    pub fields: Vec<MetaDataField>,
+}
+
+impl CustomMetaDataSettings {
+    /// Type raw values according to the field definitions, in field
+    /// definition order
+    ///
+    /// Values for undefined fields are dropped. Checkboxes without a
+    /// value are unchecked.
+    pub fn resolve(&self, raw: &CustomMetaData) -> Vec<CustomMetaDataValue> {
+        self.fields
+            .iter()
+            .filter_map(|field| {
+                let value = raw.items.iter().find(|i| i.field_id == field.id);
+                let value = match (value, &field.r#type) {
+                    (Some(item), _) => field.value(&item.value),
+                    (None, MetaDataFieldType::Checkbox) => MetaDataValue::Checkbox(false),
+                    (None, _) => return None,
+                };
+                Some(CustomMetaDataValue {
+                    id: field.id.clone(),
+                    title: field.title.clone(),
+                    value,
+                })
+            })
+            .collect()
+    }
+}
+
+/// Custom metadata field types
+#[derive(Debug, Deserialize, PartialEq, Clone, Default)]
+pub enum MetaDataFieldType {
+    #[default]
+    Text,
+    Checkbox,
+    List,
+    Date,
+}
+
+/// A custom metadata field definition
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct MetaDataField {
+    #[serde(rename = "ID")]
+    pub id: String,
+    #[serde(rename = "Type", default)]
+    pub r#type: MetaDataFieldType,
+    #[serde(rename = "Title", default)]
+    pub title: String,
+    #[serde(rename = "ListOptions", default)]
+    pub list_options: ListOptions,
+}
+
+impl MetaDataField {
+    /// Interpret a raw value according to the field type
+    ///
+    /// List values may refer to an option by ID, dates that cannot be
+    /// parsed are left as text.
+    pub fn value(&self, raw: &str) -> MetaDataValue {
+        match self.r#type {
+            MetaDataFieldType::Text => MetaDataValue::Text(raw.to_string()),
+            MetaDataFieldType::Checkbox => MetaDataValue::Checkbox(raw == "Yes"),
+            MetaDataFieldType::List => MetaDataValue::List(
+                self.list_options
+                    .options
+                    .iter()
+                    .find(|o| o.id == raw)
+                    .map_or(raw, |o| o.name.as_str())
+                    .to_string(),
+            ),
+            MetaDataFieldType::Date => match parse_timestamp(raw) {
+                Some(date) => MetaDataValue::Date(date),
+                None => MetaDataValue::Text(raw.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct ListOptions {
+    #[serde(rename = "Option", default)]
+    pub options: Vec<ListOption>,
+}
+
+/// An option of a list metadata field
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct ListOption {
+    #[serde(rename = "ID", default)]
+    pub id: String,
+    #[serde(rename = "$value", default)]
+    pub name: String,
+}
+
+/// A typed custom metadata value
+#[derive(Debug, PartialEq, Clone)]
+pub enum MetaDataValue {
+    Text(String),
+    Checkbox(bool),
+    List(String),
+    Date(DateTime<FixedOffset>),
+}
+
+impl MetaDataValue {
+    /// Returns true if the value matches the textual representation
+    ///
+    /// Checkboxes match Yes / No (or true / false), dates match either
+    /// the full timestamp or just the day, e.g. "2022-07-01".
+    pub fn matches(&self, s: &str) -> bool {
+        match self {
+            MetaDataValue::Text(t) | MetaDataValue::List(t) => t == s,
+            MetaDataValue::Checkbox(b) => match s.to_ascii_lowercase().as_str() {
+                "yes" | "true" => *b,
+                "no" | "false" => !*b,
+                _ => false,
+            },
+            MetaDataValue::Date(d) => {
+                parse_timestamp(s) == Some(*d) || d.date_naive().to_string() == s
+            }
+        }
+    }
+}
+
+impl fmt::Display for MetaDataValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MetaDataValue::Text(t) | MetaDataValue::List(t) => write!(f, "{}", t),
+            MetaDataValue::Checkbox(true) => write!(f, "Yes"),
+            MetaDataValue::Checkbox(false) => write!(f, "No"),
+            MetaDataValue::Date(d) => write!(f, "{}", d.to_rfc3339()),
+        }
+    }
+}
+
+/// A binder item's custom metadata value for a field
+#[derive(Debug, PartialEq, Clone)]
+pub struct CustomMetaDataValue {
+    /// Field ID
+    pub id: String,
+    /// Field title
+    pub title: String,
+    pub value: MetaDataValue,
+}
+
+/// The project's label definitions
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct LabelSettings {
+    #[serde(rename = "Title", default)]
+    pub title: String,
+    #[serde(rename = "Labels", default)]
+    pub labels: Labels,
+}
+
+impl LabelSettings {
+    /// Find label by ID
+    pub fn label(&self, id: i32) -> Option<&Label> {
+        self.labels.labels.iter().find(|l| l.id == id)
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct Labels {
+    #[serde(rename = "Label", default)]
+    pub labels: Vec<Label>,
+}
+
+/// A label definition
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct Label {
+    #[serde(rename = "ID")]
+    pub id: i32,
+    #[serde(rename = "Color", default)]
+    pub color: Option<Color>,
+    #[serde(rename = "$value", default)]
+    pub name: String,
+}
+
+/// The project's status definitions
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct StatusSettings {
+    #[serde(rename = "Title", default)]
+    pub title: String,
+    #[serde(rename = "StatusItems", default)]
+    pub status_items: StatusItems,
+}
+
+impl StatusSettings {
+    /// Find status by ID
+    pub fn status(&self, id: i32) -> Option<&Status> {
+        self.status_items.statuses.iter().find(|s| s.id == id)
+    }
+}
+
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct StatusItems {
+    #[serde(rename = "Status", default)]
+    pub statuses: Vec<Status>,
+}
+
+/// A status definition
+#[derive(Debug, Deserialize, PartialEq, Clone)]
+pub struct Status {
+    #[serde(rename = "ID")]
+    pub id: i32,
+    #[serde(rename = "Color", default)]
+    pub color: Option<Color>,
+    #[serde(rename = "$value", default)]
+    pub name: String,
+}
+
+/// The collections section of a project
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct Collections {
+    #[serde(rename = "Collection", default)]
+    pub collections: Vec<Collection>,
+}
+
+/// Collection types
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub enum CollectionType {
+    /// The binder itself
+    Binder,
+    /// The results of the last project search
+    SearchResults,
+    /// An explicit list of binder items
+    #[default]
+    Arbitrary,
+    /// A saved search
+    SavedSearch,
+    /// Other collection type
+    #[serde(other)]
+    Other,
+}
+
+/// A collection of binder items
+///
+/// Either an explicit list of items or a saved search.
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Collection {
+    #[serde(rename = "ID", default)]
+    pub id: String,
+    #[serde(rename = "Type", default)]
+    pub r#type: CollectionType,
+    #[serde(rename = "Color", default)]
+    pub color: Option<Color>,
+    #[serde(rename = "Title", default)]
+    pub title: String,
+    #[serde(rename = "BinderItems", default)]
+    pub binder_items: CollectionItems,
+    #[serde(rename = "SearchSettings", default)]
+    pub search_settings: Option<SearchSettings>,
+}
+
+/// The items listed in a collection
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct CollectionItems {
+    #[serde(rename = "BinderItem", default)]
+    pub items: Vec<CollectionItem>,
+}
+
+impl CollectionItems {
+    /// UUIDs of the items in collection order
+    pub fn uuids(&self) -> impl Iterator<Item = &Uuid> {
+        self.items.iter().map(|i| &i.uuid)
+    }
+}
+
+/// Reference to a binder item from a collection
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct CollectionItem {
+    #[serde(rename = "UUID")]
+    pub uuid: Uuid,
+}
+
+/// How the words of a search string are matched
+#[derive(Debug, Deserialize, PartialEq, Clone, Copy, Default)]
+pub enum SearchOperator {
+    /// Any of the words
+    Any,
+    /// All of the words
+    #[default]
+    All,
+    /// The exact phrase
+    Exact,
+    /// A regular expression
+    RegEx,
+}
+
+/// The stored criteria of a saved search collection
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct SearchSettings {
+    #[serde(rename = "Operator", default)]
+    pub operator: SearchOperator,
+    #[serde(rename = "MatchCase", deserialize_with = "de_from_yes_no", default)]
+    pub match_case: bool,
+    /// Search string
+    #[serde(rename = "Text", default)]
+    pub text: String,
+    /// Space separated list of areas searched, e.g. "Title Synopsis"
+    /// (all areas if empty)
+    #[serde(rename = "Scope", default)]
+    pub scope: String,
+}
+
+impl SearchSettings {
+    /// Returns true if the named area (e.g. "Text", "Notes") is searched
+    pub fn searches(&self, area: &str) -> bool {
+        self.scope.trim().is_empty()
+            || self
+                .scope
+                .split_whitespace()
+                .any(|s| s == "All" || s == area)
+    }
+}
+
+/// A list of keyword definitions
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct Keywords {
+    #[serde(rename = "Keyword", default)]
+    pub keywords: Vec<Keyword>,
+}
+
+impl Keywords {
+    /// Find keyword by ID anywhere in the hierarchy
+    pub fn keyword(&self, id: i32) -> Option<&Keyword> {
+        self.keywords
+            .iter()
+            .flat_map(|k| k.iter())
+            .find(|k| k.id == id)
+    }
+}
+
+/// A keyword definition, possibly with child keywords
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Keyword {
+    #[serde(rename = "ID")]
+    pub id: i32,
+    #[serde(rename = "Color", default)]
+    pub color: Option<Color>,
+    #[serde(rename = "Title", default)]
+    pub title: String,
+    #[serde(rename = "Children", default)]
+    pub children: Keywords,
+}
+
+impl Keyword {
+    /// Iterate over this keyword and its descendents
+    pub fn iter(&self) -> Box<dyn Iterator<Item = &Keyword> + '_> {
+        Box::new(std::iter::once(self).chain(self.children.keywords.iter().flat_map(|k| k.iter())))
+    }
+}
+
+/// References from a binder item to keyword definitions
+#[derive(Debug, Deserialize, PartialEq, Default)]
+pub struct KeywordRefs {
+    #[serde(rename = "KeywordID", default)]
+    pub ids: Vec<i32>,
 }
 
 /// A binder item
@@ -125,19 +1001,56 @@ pub struct BinderItemMetadata {
 pub struct BinderItem {
     #[serde(rename = "UUID", default)]
     pub uuid: Uuid,
+    /// Integer ID used by legacy projects instead of UUID
+    #[serde(rename = "ID", default)]
+    pub id: Option<String>,
     #[serde(rename = "Type", default)]
     pub r#type: BinderItemType,
+    #[serde(rename = "Created", deserialize_with = "de_from_timestamp", default)]
+    pub created: Option<DateTime<FixedOffset>>,
+    #[serde(rename = "Modified", deserialize_with = "de_from_timestamp", default)]
+    pub modified: Option<DateTime<FixedOffset>>,
     #[serde(rename = "Title", default)]
     pub title: String,
+    #[serde(rename = "MetaData", default)]
+    pub metadata: BinderItemMetadata,
+    #[serde(rename = "Keywords", default)]
+    pub keyword_refs: KeywordRefs,
     #[serde(rename = "Children", default)]
     pub children: Children,
+    /// Label resolved from metadata label ID
+    #[serde(skip)]
+    pub label: Option<Label>,
+    /// Status resolved from metadata status ID
+    #[serde(skip)]
+    pub status: Option<Status>,
+    /// Keyword titles resolved from keyword IDs
+    #[serde(skip)]
+    pub keywords: Vec<String>,
+    /// Custom metadata typed according to the project's field
+    /// definitions
+    #[serde(skip)]
+    pub custom_metadata: Vec<CustomMetaDataValue>,
 }
 
 impl BinderItem {
+    /// Whether the item itself is marked for inclusion in compile
+    pub fn include_in_compile(&self) -> bool {
+        self.metadata.include_in_compile
+    }
+
     /// Iterate over this item and its descendents
-    pub fn iter(&self) -> BinderIterator {
+    pub fn iter(&self) -> BinderIterator<'_> {
         BinderIterator::new_from_root(self)
     }
+
+    /// Apply `f` to this item and its descendents, depth first
+    fn walk_mut<F: FnMut(&mut BinderItem)>(&mut self, f: &mut F) {
+        f(self);
+        for child in self.children.binder_items.iter_mut() {
+            child.walk_mut(f);
+        }
+    }
 }
 
 /// The binder section of a project
@@ -149,7 +1062,7 @@ pub struct Binder {
 
 impl Binder {
     /// An iterator over all items in the binder
-    pub fn iter(&self) -> BinderIterator {
+    pub fn iter(&self) -> BinderIterator<'_> {
         BinderIterator::new(self.binder_items.iter().collect())
     }
 }
@@ -194,3 +1107,543 @@ impl<'a> Iterator for BinderIterator<'a> {
         }
     }
 }
+
+/// A binder item together with its position in the binder
+#[derive(Debug, Clone, PartialEq)]
+pub struct BinderEntry<'a> {
+    /// The item itself
+    pub item: &'a BinderItem,
+    /// Ancestors of the item, outermost (top-level folder) first
+    pub ancestors: Vec<&'a BinderItem>,
+    /// Index of each ancestor among its siblings, outermost first
+    pub ancestor_indices: Vec<usize>,
+    /// Index of the item among its siblings
+    pub index: usize,
+}
+
+impl<'a> BinderEntry<'a> {
+    /// An entry for a top-level item
+    pub fn root(item: &'a BinderItem, index: usize) -> Self {
+        BinderEntry {
+            item,
+            ancestors: vec![],
+            ancestor_indices: vec![],
+            index,
+        }
+    }
+
+    /// Depth in the binder (zero for top-level items)
+    pub fn depth(&self) -> usize {
+        self.ancestors.len()
+    }
+
+    /// The item's parent, unless it is a top-level item
+    pub fn parent(&self) -> Option<&'a BinderItem> {
+        self.ancestors.last().copied()
+    }
+
+    /// Titles of the item's ancestors, outermost first
+    pub fn ancestor_titles(&self) -> Vec<&'a str> {
+        self.ancestors.iter().map(|a| a.title.as_str()).collect()
+    }
+
+    /// UUIDs of the item's ancestors, outermost first
+    pub fn ancestor_uuids(&self) -> Vec<Uuid> {
+        self.ancestors.iter().map(|a| a.uuid).collect()
+    }
+
+    /// Indices among siblings from the top-level folder down to and
+    /// including the item itself, which identify it in the binder
+    /// (unlike UUIDs, which legacy projects lack)
+    pub fn position(&self) -> Vec<usize> {
+        let mut position = self.ancestor_indices.clone();
+        position.push(self.index);
+        position
+    }
+
+    /// Titles from the top-level folder down to and including the
+    /// item itself
+    pub fn path(&self) -> Vec<&'a str> {
+        let mut path = self.ancestor_titles();
+        path.push(&self.item.title);
+        path
+    }
+
+    /// Returns true if the item would be compiled, i.e. it and all its
+    /// ancestors below the top-level folder are included in compile
+    ///
+    /// The top-level folder (usually the draft) is the root of the
+    /// compile, so its own setting only affects its own text.
+    pub fn included_in_compile(&self) -> bool {
+        self.item.include_in_compile()
+            && self
+                .ancestors
+                .iter()
+                .skip(1)
+                .all(|ancestor| ancestor.include_in_compile())
+    }
+
+    /// Outline number of the item relative to its ancestor at
+    /// `depth`, as 1-based positions among siblings from just below
+    /// that ancestor down to the item, e.g. `[2, 3, 1]` for 2.3.1
+    ///
+    /// Empty if the item is itself at (or above) `depth`.
+    pub fn outline_number(&self, depth: usize) -> Vec<usize> {
+        self.position()
+            .into_iter()
+            .skip(depth + 1)
+            .map(|index| index + 1)
+            .collect()
+    }
+
+    /// Entries for the item's children
+    pub fn children(&self) -> impl DoubleEndedIterator<Item = BinderEntry<'a>> + '_ {
+        self.item
+            .children
+            .binder_items
+            .iter()
+            .enumerate()
+            .map(move |(index, child)| {
+                let mut ancestors = self.ancestors.clone();
+                ancestors.push(self.item);
+                BinderEntry {
+                    item: child,
+                    ancestors,
+                    ancestor_indices: self.position(),
+                    index,
+                }
+            })
+    }
+}
+
+/// An iterator over binder items, depth first, which tracks where
+/// each item is in the binder
+pub struct BinderPathIterator<'a> {
+    stack: Vec<BinderEntry<'a>>,
+}
+
+impl<'a> BinderPathIterator<'a> {
+    /// An iterator over the specified entries and their descendants
+    pub fn new(roots: Vec<BinderEntry<'a>>) -> BinderPathIterator<'a> {
+        BinderPathIterator {
+            stack: roots.into_iter().rev().collect(),
+        }
+    }
+}
+
+impl<'a> Iterator for BinderPathIterator<'a> {
+    type Item = BinderEntry<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let entry = self.stack.pop()?;
+        self.stack.extend(entry.children().rev());
+        Some(entry)
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    const PROJECT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ScrivenerProject Template="No" Version="2.0" Identifier="11111111-0000-0000-0000-000000000000" Creator="SCRMAC-3.2.3" Device="mac" Author="Me" Modified="2022-07-01 10:00:00 +0100" ModID="22222222-0000-0000-0000-000000000000">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder" Created="2022-01-01 10:00:00 +0000" Modified="2022-07-01 10:00:00 +0100">
+            <Title>Draft</Title>
+            <MetaData>
+                <IncludeInCompile>Yes</IncludeInCompile>
+            </MetaData>
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002" Type="Text" Created="2022-01-02 10:00:00 +0000" Modified="2022-07-02 10:00:00 +0100">
+                    <Title>Scene One</Title>
+                    <MetaData>
+                        <LabelID>0</LabelID>
+                        <StatusID>1</StatusID>
+                        <IncludeInCompile>Yes</IncludeInCompile>
+                        <CustomMetaData>
+                            <MetaDataItem>
+                                <FieldID>pov</FieldID>
+                                <Value>Anna</Value>
+                            </MetaDataItem>
+                            <MetaDataItem>
+                                <FieldID>thread</FieldID>
+                                <Value>1</Value>
+                            </MetaDataItem>
+                            <MetaDataItem>
+                                <FieldID>storydate</FieldID>
+                                <Value>1955-04-25 00:00:00 +0000</Value>
+                            </MetaDataItem>
+                        </CustomMetaData>
+                    </MetaData>
+                    <Keywords>
+                        <KeywordID>1</KeywordID>
+                        <KeywordID>3</KeywordID>
+                    </Keywords>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003" Type="ResearchFolder" Created="2022-01-01 10:00:00 +0000" Modified="2022-01-01 10:00:00 +0000">
+            <Title>Research</Title>
+        </BinderItem>
+    </Binder>
+    <LabelSettings>
+        <Title>Label</Title>
+        <DefaultLabelID>-1</DefaultLabelID>
+        <Labels>
+            <Label ID="-1">No Label</Label>
+            <Label ID="0" Color="0.698 0.847 0.976">POV: Anna</Label>
+        </Labels>
+    </LabelSettings>
+    <StatusSettings>
+        <Title>Status</Title>
+        <StatusItems>
+            <Status ID="-1">No Status</Status>
+            <Status ID="0">To Do</Status>
+            <Status ID="1">First Draft</Status>
+        </StatusItems>
+    </StatusSettings>
+    <Keywords>
+        <Keyword ID="0">
+            <Title>Characters</Title>
+            <Children>
+                <Keyword ID="1" Color="1.0 0.0 0.0">
+                    <Title>Anna</Title>
+                </Keyword>
+            </Children>
+        </Keyword>
+        <Keyword ID="2">
+            <Title>Locations</Title>
+            <Children>
+                <Keyword ID="3">
+                    <Title>Vienna</Title>
+                </Keyword>
+            </Children>
+        </Keyword>
+    </Keywords>
+    <CustomMetaDataSettings>
+        <MetaDataField Type="Text" ID="pov" Wraps="No" Align="Left">
+            <Title>POV</Title>
+        </MetaDataField>
+        <MetaDataField Type="Checkbox" ID="done" Default="No">
+            <Title>Done</Title>
+        </MetaDataField>
+        <MetaDataField Type="List" ID="thread">
+            <Title>Thread</Title>
+            <ListOptions>
+                <Option ID="0">Main</Option>
+                <Option ID="1">Romance</Option>
+            </ListOptions>
+        </MetaDataField>
+        <MetaDataField Type="Date" ID="storydate">
+            <Title>Story Date</Title>
+        </MetaDataField>
+    </CustomMetaDataSettings>
+    <Collections>
+        <Collection ID="Binder" Type="Binder">
+            <Title>Binder</Title>
+        </Collection>
+        <Collection ID="8C4E7B2A-3B1C-4C3A-9C5F-1E6B3D2A4F10" Type="Arbitrary" Color="0.5 0.5 1.0">
+            <Title>Needs revision</Title>
+            <BinderItems>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003"/>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002"/>
+            </BinderItems>
+        </Collection>
+        <Collection ID="3F1B6E2C-9A7D-4E5B-8C1A-2D3E4F5A6B7C" Type="SavedSearch">
+            <Title>Scenes</Title>
+            <SearchSettings Operator="Any" MatchCase="No">
+                <Text>scene chapter</Text>
+                <Scope>Title</Scope>
+            </SearchSettings>
+        </Collection>
+    </Collections>
+</ScrivenerProject>"#;
+
+    #[test]
+    pub fn test_labels_and_statuses() {
+        let project = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        assert_eq!(project.labels().len(), 2);
+        assert_eq!(project.statuses().len(), 3);
+        assert_eq!(project.labels()[1].name, "POV: Anna");
+        assert_eq!(project.labels()[1].color.unwrap().to_string(), "#B2D8F9");
+
+        let draft = project.draft();
+        assert_eq!(draft.label.as_ref().unwrap().name, "No Label");
+        assert_eq!(draft.status.as_ref().unwrap().name, "No Status");
+
+        let scene = &draft.children.binder_items[0];
+        assert_eq!(scene.label.as_ref().unwrap().name, "POV: Anna");
+        assert_eq!(scene.status.as_ref().unwrap().name, "First Draft");
+    }
+
+    #[test]
+    pub fn test_timestamps() {
+        let project = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        let scene = &project.draft().children.binder_items[0];
+        assert_eq!(
+            scene.created.unwrap().to_rfc3339(),
+            "2022-01-02T10:00:00+00:00"
+        );
+        assert_eq!(
+            scene.modified.unwrap().to_rfc3339(),
+            "2022-07-02T10:00:00+01:00"
+        );
+        assert_eq!(project.item(&Uuid::nil()), None);
+
+        let source = r#"<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder" Created="yesterday" Modified="2022-07-01 10:00:00 +0100">
+            <Title>Draft</Title>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>"#;
+        let project = ScrivenerProject::parse(source.as_bytes()).unwrap();
+        assert_eq!(project.draft().created, None);
+        assert!(project.draft().modified.is_some());
+    }
+
+    #[test]
+    pub fn test_legacy_project() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<ScrivenerProject Version="1.0" Creator="SCRWIN-1.9.16.0" Identifier="11111111-0000-0000-0000-000000000000">
+    <Binder>
+        <BinderItem ID="0" Type="DraftFolder" Created="2015-01-01 10:00:00 +0000" Modified="2015-01-01 10:00:00 +0000">
+            <Title>Manuscript</Title>
+            <Children>
+                <BinderItem ID="12" Type="Text" Created="2015-01-01 10:00:00 +0000" Modified="2015-01-01 10:00:00 +0000">
+                    <Title>Chapter One</Title>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>"#;
+        let project = ScrivenerProject::parse(source.as_bytes()).unwrap();
+        assert_eq!(project.format(), ProjectFormat::Legacy);
+        let chapter = &project.draft().children.binder_items[0];
+        assert_eq!(chapter.id.as_deref(), Some("12"));
+        assert_eq!(chapter.uuid, Uuid::nil());
+
+        let current = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        assert_eq!(current.format(), ProjectFormat::Current);
+
+        let mut document = ProjectDocument::parse(source.as_bytes()).unwrap();
+        assert_eq!(document.format(), ProjectFormat::Legacy);
+        assert!(matches!(
+            document.create_text_item(&Uuid::nil(), "Chapter Two"),
+            Err(ScryError::UnsupportedInLegacy(_))
+        ));
+    }
+
+    #[test]
+    pub fn test_binder_mutation() {
+        let mut document = ProjectDocument::parse(PROJECT.as_bytes()).unwrap();
+        let draft = Uuid::parse_str("AAAAAAAA-0000-0000-0000-000000000001").unwrap();
+        let scene = Uuid::parse_str("AAAAAAAA-0000-0000-0000-000000000002").unwrap();
+        let research = Uuid::parse_str("AAAAAAAA-0000-0000-0000-000000000003").unwrap();
+
+        assert!(matches!(
+            document.move_item(&draft, &scene),
+            Err(ScryError::InvalidMove(_, _))
+        ));
+        assert!(matches!(
+            document.rename_item(&Uuid::nil(), "Nothing"),
+            Err(ScryError::UnknownItem(_))
+        ));
+
+        let created = document.create_text_item(&draft, "Scene Two").unwrap();
+        assert_eq!(document.created_items(), &[created]);
+        document.move_item(&scene, &research).unwrap();
+        document.rename_item(&scene, "Scene One (cut)").unwrap();
+        document.set_include_in_compile(&created, false).unwrap();
+        document.set_label(&created, Some("POV: Anna")).unwrap();
+        document.set_status(&created, Some("To Do")).unwrap();
+        document.set_status(&scene, None).unwrap();
+        assert!(matches!(
+            document.set_label(&created, Some("Nope")),
+            Err(ScryError::UnknownLabel(_))
+        ));
+
+        let project = document.project().unwrap();
+        let draft_children: Vec<_> = project
+            .draft()
+            .children
+            .binder_items
+            .iter()
+            .map(|i| i.uuid)
+            .collect();
+        assert_eq!(draft_children, vec![created]);
+
+        let new_item = project.item(&created).unwrap();
+        assert_eq!(new_item.r#type, BinderItemType::Text);
+        assert_eq!(new_item.title, "Scene Two");
+        assert!(!new_item.metadata.include_in_compile);
+        assert_eq!(new_item.label.as_ref().unwrap().name, "POV: Anna");
+        assert_eq!(new_item.status.as_ref().unwrap().name, "To Do");
+
+        let research_item = project.item(&research).unwrap();
+        assert_eq!(research_item.children.binder_items[0].uuid, scene);
+        let moved = project.item(&scene).unwrap();
+        assert_eq!(moved.title, "Scene One (cut)");
+        assert_eq!(moved.metadata.status_id, -1);
+        assert_eq!(moved.keywords.len(), 2);
+    }
+
+    #[test]
+    pub fn test_binder_entries() {
+        let project = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        let entries: Vec<_> = project.entries().collect();
+        assert_eq!(entries.len(), 3);
+
+        let scene = &entries[1];
+        assert_eq!(scene.item.title, "Scene One");
+        assert_eq!(scene.depth(), 1);
+        assert_eq!(scene.index, 0);
+        assert_eq!(scene.position(), vec![0, 0]);
+        assert_eq!(scene.path(), vec!["Draft", "Scene One"]);
+        assert_eq!(scene.ancestor_uuids(), vec![project.draft().uuid]);
+        assert_eq!(scene.parent().map(|p| p.uuid), Some(project.draft().uuid));
+
+        assert!(scene.included_in_compile());
+
+        let research = &entries[2];
+        assert_eq!(research.depth(), 0);
+        assert!(!research.included_in_compile());
+        assert_eq!(research.index, 1);
+        assert_eq!(research.parent(), None);
+        assert_eq!(research.outline_number(0), Vec::<usize>::new());
+        assert_eq!(scene.outline_number(0), vec![1]);
+        assert_eq!(scene.outline_number(1), Vec::<usize>::new());
+    }
+
+    #[test]
+    pub fn test_included_in_compile() {
+        let source = r#"<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder">
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002" Type="Folder">
+                    <Children>
+                        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003" Type="Text">
+                            <MetaData><IncludeInCompile>Yes</IncludeInCompile></MetaData>
+                        </BinderItem>
+                    </Children>
+                </BinderItem>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000004" Type="Text">
+                    <MetaData><IncludeInCompile>Yes</IncludeInCompile></MetaData>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+</ScrivenerProject>"#;
+        let project = ScrivenerProject::parse(source.as_bytes()).unwrap();
+        let compiled: Vec<_> = project
+            .entries()
+            .map(|e| (e.item.uuid.as_u128() as u8, e.included_in_compile()))
+            .collect();
+        assert_eq!(
+            compiled,
+            vec![(1, false), (2, false), (3, false), (4, true)]
+        );
+    }
+
+    #[test]
+    pub fn test_parse_item_reference() {
+        let uuid = Uuid::parse_str("AAAAAAAA-0000-0000-0000-000000000002").unwrap();
+        assert_eq!(
+            parse_item_reference("AAAAAAAA-0000-0000-0000-000000000002"),
+            Some(uuid)
+        );
+        assert_eq!(
+            parse_item_reference(
+                "x-scrivener-item:///Users/me/Novel.scriv?id=AAAAAAAA-0000-0000-0000-000000000002"
+            ),
+            Some(uuid)
+        );
+        assert_eq!(
+            parse_item_reference("x-scrivener-item:///Users/me/Novel.scriv"),
+            None
+        );
+        assert_eq!(parse_item_reference("Scene One"), None);
+    }
+
+    #[test]
+    pub fn test_document_round_trip() {
+        let source = PROJECT.replace(
+            "<Binder>",
+            "<Unmodelled Setting=\"kept\"><!-- note --></Unmodelled>\n    <Binder>",
+        );
+        let document = ProjectDocument::parse(source.as_bytes()).unwrap();
+        let unmodelled = document.root().child("Unmodelled").unwrap();
+        assert_eq!(unmodelled.attribute("Setting"), Some("kept"));
+
+        let bytes = document.to_bytes().unwrap();
+        assert_eq!(ProjectDocument::parse(&bytes[..]).unwrap(), document);
+        assert_eq!(
+            document.project().unwrap(),
+            ScrivenerProject::parse(PROJECT.as_bytes()).unwrap()
+        );
+    }
+
+    #[test]
+    pub fn test_keywords() {
+        let project = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        let titles: Vec<_> = project.keywords()[0]
+            .iter()
+            .map(|k| k.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Characters", "Anna"]);
+        assert_eq!(project.keywords.keyword(3).unwrap().title, "Vienna");
+
+        let scene = &project.draft().children.binder_items[0];
+        assert_eq!(scene.keywords, vec!["Anna", "Vienna"]);
+        assert!(project.draft().keywords.is_empty());
+    }
+
+    #[test]
+    pub fn test_custom_metadata() {
+        let project = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        assert_eq!(project.custom_metadata_fields().len(), 4);
+
+        let scene = &project.draft().children.binder_items[0];
+        let values: Vec<_> = scene
+            .custom_metadata
+            .iter()
+            .map(|v| (v.title.as_str(), v.value.to_string()))
+            .collect();
+        assert_eq!(
+            values,
+            vec![
+                ("POV", "Anna".to_string()),
+                ("Done", "No".to_string()),
+                ("Thread", "Romance".to_string()),
+                ("Story Date", "1955-04-25T00:00:00+00:00".to_string()),
+            ]
+        );
+        assert!(scene.custom_metadata[3].value.matches("1955-04-25"));
+        assert!(scene.custom_metadata[1].value.matches("no"));
+    }
+
+    #[test]
+    pub fn test_collections() {
+        let project = ScrivenerProject::parse(PROJECT.as_bytes()).unwrap();
+        assert_eq!(project.collections().len(), 3);
+        assert_eq!(project.collections()[0].r#type, CollectionType::Binder);
+
+        let revise = project.collection("Needs revision").unwrap();
+        assert_eq!(revise.r#type, CollectionType::Arbitrary);
+        let titles: Vec<_> = revise
+            .binder_items
+            .uuids()
+            .filter_map(|uuid| project.item(uuid))
+            .map(|i| i.title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Research", "Scene One"]);
+
+        let scenes = project.collection("Scenes").unwrap();
+        let search = scenes.search_settings.as_ref().unwrap();
+        assert_eq!(search.operator, SearchOperator::Any);
+        assert_eq!(search.text, "scene chapter");
+        assert!(search.searches("Title"));
+        assert!(!search.searches("Text"));
+    }
+}
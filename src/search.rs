@@ -0,0 +1,132 @@
+//! Evaluation of collections, including saved searches
+//!
+//! Arbitrary collections simply list binder items. Saved searches
+//! store search criteria which we evaluate, as far as is practical,
+//! against the title, synopsis, text, notes, keywords, label, status
+//! and custom metadata of each item outside the trash.
+use crate::bundle::Bundle;
+use crate::error::Result;
+use crate::extract::{ContentIterator, ContentSpec};
+use crate::scrivx::{
+    BinderItem, BinderItemType, Collection, CollectionType, ScrivenerProject, SearchOperator,
+    SearchSettings,
+};
+use regex::{Regex, RegexBuilder};
+use std::collections::HashSet;
+
+/// Return the items of a collection in collection order
+pub fn collection_items<'a>(
+    project: &'a ScrivenerProject,
+    bundle: &Bundle,
+    collection: &Collection,
+) -> Result<Vec<&'a BinderItem>> {
+    if let Some(ref settings) = collection.search_settings {
+        let search = Search::new(settings)?;
+        Ok(untrashed_items(project)
+            .filter(|it| search.matches(bundle, it))
+            .collect())
+    } else if collection.r#type == CollectionType::Binder {
+        Ok(untrashed_items(project).collect())
+    } else {
+        Ok(collection
+            .binder_items
+            .uuids()
+            .filter_map(|uuid| project.item(uuid))
+            .collect())
+    }
+}
+
+/// All items in binder order, except those in the trash
+fn untrashed_items(project: &ScrivenerProject) -> impl Iterator<Item = &BinderItem> {
+    project
+        .binder
+        .binder_items
+        .iter()
+        .filter(|it| it.r#type != BinderItemType::TrashFolder)
+        .flat_map(|it| it.iter())
+}
+
+/// Compiled search criteria
+struct Search<'s> {
+    settings: &'s SearchSettings,
+    /// Patterns to look for
+    patterns: Vec<Regex>,
+}
+
+impl<'s> Search<'s> {
+    /// Compile the search settings
+    fn new(settings: &'s SearchSettings) -> Result<Self> {
+        let sources: Vec<String> = match settings.operator {
+            SearchOperator::Any | SearchOperator::All => settings
+                .text
+                .split_whitespace()
+                .map(regex::escape)
+                .collect(),
+            SearchOperator::Exact => vec![regex::escape(settings.text.trim())],
+            SearchOperator::RegEx => vec![settings.text.clone()],
+        };
+
+        let patterns = sources
+            .iter()
+            .map(|s| {
+                RegexBuilder::new(s)
+                    .case_insensitive(!settings.match_case)
+                    .build()
+            })
+            .collect::<std::result::Result<_, _>>()?;
+
+        Ok(Search { settings, patterns })
+    }
+
+    /// Returns true if the item's searched text satisfies the criteria
+    fn matches(&self, bundle: &Bundle, item: &BinderItem) -> bool {
+        let text = self.searched_text(bundle, item).join("\n");
+        match self.settings.operator {
+            SearchOperator::Any => self.patterns.iter().any(|p| p.is_match(&text)),
+            _ => !self.patterns.is_empty() && self.patterns.iter().all(|p| p.is_match(&text)),
+        }
+    }
+
+    /// Gather all the item's text in the areas searched
+    fn searched_text(&self, bundle: &Bundle, item: &BinderItem) -> Vec<String> {
+        let mut text = vec![];
+
+        if self.settings.searches("Title") {
+            text.push(item.title.clone());
+        }
+        if self.settings.searches("Keywords") {
+            text.extend(item.keywords.iter().cloned());
+        }
+        if self.settings.searches("Label") {
+            text.extend(item.label.iter().map(|l| l.name.clone()));
+        }
+        if self.settings.searches("Status") {
+            text.extend(item.status.iter().map(|s| s.name.clone()));
+        }
+        if self.settings.searches("Metadata") {
+            text.extend(item.custom_metadata.iter().map(|m| m.value.to_string()));
+        }
+
+        let mut content_specs = HashSet::new();
+        if self.settings.searches("Synopsis") {
+            content_specs.insert(ContentSpec::Synopsis);
+        }
+        if self.settings.searches("Text") {
+            content_specs.insert(ContentSpec::Content);
+            content_specs.insert(ContentSpec::Inlines);
+        }
+        if self.settings.searches("Notes") {
+            content_specs.insert(ContentSpec::Notes);
+        }
+        if !content_specs.is_empty() {
+            text.extend(ContentIterator::new(
+                item.uuid,
+                item.title.clone(),
+                bundle.binder_item_content(item),
+                &content_specs,
+            ));
+        }
+
+        text
+    }
+}
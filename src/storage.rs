@@ -0,0 +1,249 @@
+//! Storage from which project files are read
+//!
+//! A project is normally a directory on the file system but may also
+//! be read in place from a zipped backup (`.scriv.zip`). Only the file
+//! system supports writing.
+use chrono::{DateTime, Local, NaiveDate, TimeZone};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Component, Path, PathBuf};
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+/// Access to the files of a project
+pub trait Storage {
+    /// Read the entire file at `path`
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>>;
+    /// Returns true if `path` is a file
+    fn is_file(&self, path: &Path) -> bool;
+    /// Paths of all the files directly within the directory `dir`
+    fn list(&self, dir: &Path) -> Vec<PathBuf>;
+    /// When the file at `path` was last modified, if known
+    fn modified(&self, _path: &Path) -> Option<DateTime<Local>> {
+        None
+    }
+    /// Replace the file at `path` with `data` atomically, so that
+    /// readers see either the old or the new contents
+    fn write(&self, path: &Path, _data: &[u8]) -> io::Result<()> {
+        Err(read_only(path))
+    }
+    /// Create the directory `dir` and any missing parents
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        Err(read_only(dir))
+    }
+}
+
+/// Error for attempts to write to read-only storage
+fn read_only(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "cannot write {}: project storage is read-only",
+            path.display()
+        ),
+    )
+}
+
+/// Files on the file system
+#[derive(Default)]
+pub struct DirectoryStorage;
+
+impl Storage for DirectoryStorage {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        fs::read(path)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        path.is_file()
+    }
+
+    fn list(&self, dir: &Path) -> Vec<PathBuf> {
+        match dir.read_dir() {
+            Ok(entries) => entries
+                .flatten()
+                .map(|e| e.path())
+                .filter(|p| p.is_file())
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn modified(&self, path: &Path) -> Option<DateTime<Local>> {
+        fs::metadata(path)
+            .and_then(|m| m.modified())
+            .ok()
+            .map(DateTime::from)
+    }
+
+    fn write(&self, path: &Path, data: &[u8]) -> io::Result<()> {
+        let name = path
+            .file_name()
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no file name"))?;
+        let mut temp_name = OsString::from(".");
+        temp_name.push(name);
+        temp_name.push(".tmp");
+        let temp = path.with_file_name(temp_name);
+
+        let mut file = File::create(&temp)?;
+        file.write_all(data)?;
+        file.sync_all()?;
+        fs::rename(&temp, path)
+    }
+
+    fn create_dir(&self, dir: &Path) -> io::Result<()> {
+        fs::create_dir_all(dir)
+    }
+}
+
+/// Files within a zip archive
+///
+/// Paths are relative to the root of the archive.
+pub struct ZipStorage {
+    archive: RefCell<ZipArchive<File>>,
+    /// Archive names of all files
+    names: HashSet<String>,
+    /// Paths of the files in each directory, by archive name of the
+    /// directory
+    directories: HashMap<String, Vec<PathBuf>>,
+}
+
+impl ZipStorage {
+    /// Open a zip archive, indexing its files
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let archive = ZipArchive::new(File::open(path)?).map_err(zip_error)?;
+        let names: HashSet<String> = archive
+            .file_names()
+            .filter(|name| !name.ends_with('/'))
+            .map(|name| archive_name(Path::new(name)))
+            .collect();
+        let mut directories: HashMap<String, Vec<PathBuf>> = HashMap::new();
+        for name in &names {
+            let path = PathBuf::from(name);
+            let dir = path.parent().map(archive_name).unwrap_or_default();
+            directories.entry(dir).or_default().push(path);
+        }
+        for files in directories.values_mut() {
+            files.sort();
+        }
+        Ok(ZipStorage {
+            archive: RefCell::new(archive),
+            names,
+            directories,
+        })
+    }
+
+    /// Find the first .scrivx file in the archive
+    pub fn find_scrivx(&self) -> Option<PathBuf> {
+        let mut candidates: Vec<PathBuf> = self
+            .names
+            .iter()
+            .map(PathBuf::from)
+            .filter(|p| p.extension().is_some_and(|e| e == "scrivx"))
+            .collect();
+        candidates.sort();
+        candidates.sort_by_key(|p| p.components().count());
+        candidates.into_iter().next()
+    }
+}
+
+/// Archive name for a path (always '/' separated)
+fn archive_name(path: &Path) -> String {
+    path.components()
+        .filter_map(|c| match c {
+            Component::Normal(s) => Some(s.to_string_lossy()),
+            _ => None,
+        })
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Convert a zip error to an IO error
+fn zip_error(e: ZipError) -> io::Error {
+    match e {
+        ZipError::Io(e) => e,
+        ZipError::FileNotFound => io::Error::new(io::ErrorKind::NotFound, e),
+        _ => io::Error::new(io::ErrorKind::InvalidData, e),
+    }
+}
+
+impl Storage for ZipStorage {
+    fn read(&self, path: &Path) -> io::Result<Vec<u8>> {
+        let mut archive = self.archive.borrow_mut();
+        let mut file = archive.by_name(&archive_name(path)).map_err(zip_error)?;
+        let mut data = Vec::with_capacity(file.size() as usize);
+        file.read_to_end(&mut data)?;
+        Ok(data)
+    }
+
+    fn is_file(&self, path: &Path) -> bool {
+        self.names.contains(&archive_name(path))
+    }
+
+    fn list(&self, dir: &Path) -> Vec<PathBuf> {
+        self.directories
+            .get(&archive_name(dir))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Zip entries record local time without a zone
+    fn modified(&self, path: &Path) -> Option<DateTime<Local>> {
+        let mut archive = self.archive.borrow_mut();
+        let time = archive.by_name(&archive_name(path)).ok()?.last_modified();
+        let naive =
+            NaiveDate::from_ymd_opt(time.year().into(), time.month().into(), time.day().into())?
+                .and_hms_opt(
+                    time.hour().into(),
+                    time.minute().into(),
+                    time.second().into(),
+                )?;
+        Local.from_local_datetime(&naive).earliest()
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use zip::write::{FileOptions, ZipWriter};
+
+    #[test]
+    pub fn test_zip_storage() {
+        let file = tempfile::Builder::new()
+            .prefix("scry-test-")
+            .suffix(".scriv.zip")
+            .tempfile()
+            .unwrap();
+        let path = file.path();
+        {
+            let mut writer = ZipWriter::new(File::create(path).unwrap());
+            writer
+                .add_directory("proj.scriv/", FileOptions::default())
+                .unwrap();
+            writer
+                .start_file("proj.scriv/proj.scrivx", FileOptions::default())
+                .unwrap();
+            writer.write_all(b"<ScrivenerProject/>").unwrap();
+            writer
+                .start_file(
+                    "proj.scriv/Files/Data/X/content.rtf",
+                    FileOptions::default(),
+                )
+                .unwrap();
+            writer.write_all(b"{\\rtf1 x}").unwrap();
+            writer.finish().unwrap();
+        }
+
+        let storage = ZipStorage::open(path).unwrap();
+        let scrivx = storage.find_scrivx().unwrap();
+        assert_eq!(scrivx, Path::new("proj.scriv/proj.scrivx"));
+        assert_eq!(storage.read(&scrivx).unwrap(), b"<ScrivenerProject/>");
+
+        let folder = Path::new("proj.scriv/Files/Data/X");
+        assert_eq!(storage.list(folder), vec![folder.join("content.rtf")]);
+        assert!(storage.is_file(&folder.join("content.rtf")));
+        assert!(!storage.is_file(&folder.join("notes.rtf")));
+    }
+}
@@ -0,0 +1,153 @@
+//! Outline listing of the binder
+//!
+//! Prints selected items one per line, indented by depth, e.g.
+//!
+//! ```text
+//! Draft [DraftFolder]
+//!   Part One [Folder]
+//!     Scene One [Text] label="POV: Anna" status="First Draft" words=1250
+//! ```
+//!
+//! Depths are relative to the shallowest selected item so that a
+//! selection deep in the binder starts at the left margin.
+use crate::bundle::Bundle;
+use crate::extract::{ContentIterator, ContentSpec};
+use crate::scrivx::{BinderEntry, BinderItem};
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+/// What to show in a tree listing
+#[derive(Clone, Default)]
+pub struct TreeOptions {
+    /// Show item UUIDs
+    pub uuids: bool,
+    /// Show item labels
+    pub labels: bool,
+    /// Show item statuses
+    pub statuses: bool,
+    /// Show word counts of item content
+    pub word_counts: bool,
+    /// Omit items deeper than this (relative to the shallowest
+    /// selected item)
+    pub max_depth: Option<usize>,
+}
+
+/// Count the words in an item's content
+pub fn word_count(bundle: &Bundle, item: &BinderItem) -> usize {
+    let mut content_specs = HashSet::new();
+    content_specs.insert(ContentSpec::Content);
+    ContentIterator::new(
+        item.uuid,
+        item.title.clone(),
+        bundle.binder_item_content(item),
+        &content_specs,
+    )
+    .map(|paragraph| paragraph.split_whitespace().count())
+    .sum()
+}
+
+/// Write a tree listing of the entries
+pub fn write_tree<'a, W, I>(
+    out: &mut W,
+    bundle: &Bundle,
+    entries: I,
+    options: &TreeOptions,
+) -> io::Result<()>
+where
+    W: Write,
+    I: IntoIterator<Item = BinderEntry<'a>>,
+{
+    let entries: Vec<_> = entries.into_iter().collect();
+    let base = entries.iter().map(|e| e.depth()).min().unwrap_or(0);
+
+    for entry in &entries {
+        let depth = entry.depth() - base;
+        if options.max_depth.is_some_and(|max| depth > max) {
+            continue;
+        }
+
+        let item = entry.item;
+        write!(
+            out,
+            "{}{} [{}]",
+            "  ".repeat(depth),
+            item.title,
+            item.r#type
+        )?;
+        if options.uuids {
+            write!(out, " uuid={}", item.uuid.to_string().to_ascii_uppercase())?;
+        }
+        if options.labels {
+            if let Some(label) = item.label.as_ref().filter(|l| l.id >= 0) {
+                write!(out, " label={:?}", label.name)?;
+            }
+        }
+        if options.statuses {
+            if let Some(status) = item.status.as_ref().filter(|s| s.id >= 0) {
+                write!(out, " status={:?}", status.name)?;
+            }
+        }
+        if options.word_counts {
+            write!(out, " words={}", word_count(bundle, item))?;
+        }
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use crate::scrivx::{ProjectFormat, ScrivenerProject};
+
+    #[test]
+    pub fn test_write_tree() {
+        let source = r#"<ScrivenerProject Version="2.0">
+    <Binder>
+        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000001" Type="DraftFolder">
+            <Title>Draft</Title>
+            <Children>
+                <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000002" Type="Folder">
+                    <Title>Part One</Title>
+                    <Children>
+                        <BinderItem UUID="AAAAAAAA-0000-0000-0000-000000000003" Type="Text">
+                            <Title>Scene One</Title>
+                            <MetaData><StatusID>0</StatusID></MetaData>
+                        </BinderItem>
+                    </Children>
+                </BinderItem>
+            </Children>
+        </BinderItem>
+    </Binder>
+    <StatusSettings>
+        <StatusItems>
+            <Status ID="-1">No Status</Status>
+            <Status ID="0">To Do</Status>
+        </StatusItems>
+    </StatusSettings>
+</ScrivenerProject>"#;
+        let project = ScrivenerProject::parse(source.as_bytes()).unwrap();
+        let bundle = Bundle::new("/nonexistent", ProjectFormat::Current);
+        let options = TreeOptions {
+            statuses: true,
+            word_counts: true,
+            ..Default::default()
+        };
+
+        let mut out = vec![];
+        write_tree(&mut out, &bundle, project.entries(), &options).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "Draft [DraftFolder] words=0\n  Part One [Folder] words=0\n    Scene One [Text] status=\"To Do\" words=0\n"
+        );
+
+        let options = TreeOptions {
+            max_depth: Some(0),
+            ..Default::default()
+        };
+        let mut out = vec![];
+        write_tree(&mut out, &bundle, project.entries().skip(1), &options).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "Part One [Folder]\n");
+    }
+}
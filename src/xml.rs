@@ -0,0 +1,317 @@
+//! A lossless XML document tree
+//!
+//! The typed project model only retains what scry understands. To
+//! change a project safely we also need to keep everything else: the
+//! XML declaration, unknown elements and attributes, comments and the
+//! whitespace between elements. This module reads XML into a simple
+//! tree that preserves all of that, in document order, and writes it
+//! back out again.
+//!
+//! Text and attribute values are held unescaped and escaped again on
+//! output, so entity usage may differ from the source but the document
+//! is semantically identical.
+use quick_xml::events::{BytesCData, BytesDecl, BytesEnd, BytesStart, BytesText, Event};
+use quick_xml::{Reader, Writer};
+use std::io::{BufRead, Write};
+
+/// A node within an XML document
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Element(Element),
+    /// Character data (unescaped)
+    Text(String),
+    /// A CDATA section
+    CData(String),
+    /// A comment (verbatim)
+    Comment(String),
+    /// An XML declaration (verbatim, e.g. `xml version="1.0"`)
+    Decl(String),
+    /// A processing instruction (verbatim)
+    PI(String),
+    /// A document type declaration (verbatim)
+    DocType(String),
+}
+
+/// An XML element with its attributes and children in document order
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Element {
+    pub name: String,
+    /// Attribute names and (unescaped) values
+    pub attributes: Vec<(String, String)>,
+    pub children: Vec<Node>,
+}
+
+impl Element {
+    /// Create an empty element
+    pub fn new<T: Into<String>>(name: T) -> Self {
+        Element {
+            name: name.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Value of the named attribute
+    pub fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(k, _)| k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Set the value of an attribute, keeping its position if it
+    /// already exists
+    pub fn set_attribute<K: Into<String>, V: Into<String>>(&mut self, name: K, value: V) {
+        let name = name.into();
+        let value = value.into();
+        match self.attributes.iter_mut().find(|(k, _)| *k == name) {
+            Some(attr) => attr.1 = value,
+            None => self.attributes.push((name, value)),
+        }
+    }
+
+    /// Remove an attribute, returning its value
+    pub fn remove_attribute(&mut self, name: &str) -> Option<String> {
+        let index = self.attributes.iter().position(|(k, _)| k == name)?;
+        Some(self.attributes.remove(index).1)
+    }
+
+    /// Iterate over child elements
+    pub fn elements(&self) -> impl Iterator<Item = &Element> {
+        self.children.iter().filter_map(|n| match n {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    /// Iterate mutably over child elements
+    pub fn elements_mut(&mut self) -> impl Iterator<Item = &mut Element> {
+        self.children.iter_mut().filter_map(|n| match n {
+            Node::Element(e) => Some(e),
+            _ => None,
+        })
+    }
+
+    /// First child element with the specified name
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.elements().find(|e| e.name == name)
+    }
+
+    /// First child element with the specified name (mutable)
+    pub fn child_mut(&mut self, name: &str) -> Option<&mut Element> {
+        self.elements_mut().find(|e| e.name == name)
+    }
+
+    /// First child element with the specified name, appending a new
+    /// empty one if there is none
+    pub fn child_or_insert(&mut self, name: &str) -> &mut Element {
+        if self.child(name).is_none() {
+            self.children.push(Node::Element(Element::new(name)));
+        }
+        self.child_mut(name).unwrap()
+    }
+
+    /// Concatenated text and CDATA content of this element (not
+    /// including descendants)
+    pub fn text(&self) -> String {
+        self.children
+            .iter()
+            .filter_map(|n| match n {
+                Node::Text(s) | Node::CData(s) => Some(s.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Replace the element's content with text
+    pub fn set_text<T: Into<String>>(&mut self, text: T) {
+        self.children = vec![Node::Text(text.into())];
+    }
+
+    /// Write the element and its descendants
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> quick_xml::Result<()> {
+        let mut start = BytesStart::owned_name(self.name.as_bytes());
+        for (k, v) in &self.attributes {
+            start.push_attribute((k.as_str(), v.as_str()));
+        }
+        if self.children.is_empty() {
+            writer.write_event(Event::Empty(start))
+        } else {
+            writer.write_event(Event::Start(start))?;
+            for child in &self.children {
+                child.write(writer)?;
+            }
+            writer.write_event(Event::End(BytesEnd::borrowed(self.name.as_bytes())))
+        }
+    }
+}
+
+impl Node {
+    /// Write the node
+    fn write<W: Write>(&self, writer: &mut Writer<W>) -> quick_xml::Result<()> {
+        match self {
+            Node::Element(e) => e.write(writer),
+            Node::Text(s) => writer.write_event(Event::Text(BytesText::from_plain_str(s))),
+            Node::CData(s) => writer.write_event(Event::CData(BytesCData::from_str(s))),
+            Node::Comment(s) => {
+                writer.write_event(Event::Comment(BytesText::from_escaped_str(s.as_str())))
+            }
+            Node::Decl(s) => writer.write_event(Event::Decl(BytesDecl::from_start(
+                BytesStart::borrowed(s.as_bytes(), 3),
+            ))),
+            Node::PI(s) => writer.write_event(Event::PI(BytesText::from_escaped_str(s.as_str()))),
+            Node::DocType(s) => {
+                writer.write_event(Event::DocType(BytesText::from_escaped_str(s.as_str())))
+            }
+        }
+    }
+}
+
+/// An XML document: a root element with whatever precedes and
+/// follows it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Document {
+    /// Declaration, comments, whitespace etc. before the root element
+    pub prolog: Vec<Node>,
+    pub root: Element,
+    /// Anything after the root element
+    pub epilog: Vec<Node>,
+}
+
+impl Document {
+    /// Read a document
+    pub fn parse<T: BufRead>(input: T) -> quick_xml::Result<Self> {
+        let mut reader = Reader::from_reader(input);
+        reader.trim_text(false);
+        reader.check_end_names(true);
+
+        let mut buf = Vec::new();
+        let mut prolog = vec![];
+        let mut epilog = vec![];
+        let mut root = None;
+        // elements opened but not yet closed
+        let mut open: Vec<Element> = vec![];
+
+        loop {
+            let node = match reader.read_event(&mut buf)? {
+                Event::Start(e) => {
+                    open.push(start_element(&reader, &e)?);
+                    None
+                }
+                Event::End(_) => open.pop().map(Node::Element),
+                Event::Empty(e) => Some(Node::Element(start_element(&reader, &e)?)),
+                Event::Text(e) if e.is_empty() => None,
+                Event::Text(e) => Some(Node::Text(e.unescape_and_decode(&reader)?)),
+                Event::CData(e) => Some(Node::CData(reader.decode(&e)?.to_string())),
+                Event::Comment(e) => Some(Node::Comment(reader.decode(&e)?.to_string())),
+                Event::Decl(e) => Some(Node::Decl(reader.decode(&e)?.to_string())),
+                Event::PI(e) => Some(Node::PI(reader.decode(&e)?.to_string())),
+                Event::DocType(e) => Some(Node::DocType(reader.decode(&e)?.to_string())),
+                Event::Eof => break,
+            };
+
+            if let Some(node) = node {
+                match (open.last_mut(), node) {
+                    (Some(parent), node) => parent.children.push(node),
+                    (None, Node::Element(e)) if root.is_none() => root = Some(e),
+                    (None, node) if root.is_none() => prolog.push(node),
+                    (None, node) => epilog.push(node),
+                }
+            }
+            buf.clear();
+        }
+
+        match root {
+            Some(root) if open.is_empty() => Ok(Document {
+                prolog,
+                root,
+                epilog,
+            }),
+            _ => Err(quick_xml::Error::UnexpectedEof(
+                "document has no complete root element".to_string(),
+            )),
+        }
+    }
+
+    /// Write the document
+    pub fn write<W: Write>(&self, output: W) -> quick_xml::Result<()> {
+        let mut writer = Writer::new(output);
+        for node in &self.prolog {
+            node.write(&mut writer)?;
+        }
+        self.root.write(&mut writer)?;
+        for node in &self.epilog {
+            node.write(&mut writer)?;
+        }
+        Ok(())
+    }
+
+    /// Write the document to a byte vector
+    pub fn to_bytes(&self) -> quick_xml::Result<Vec<u8>> {
+        let mut bytes = vec![];
+        self.write(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+/// Construct an element (without children) from its start tag
+fn start_element<B: BufRead>(reader: &Reader<B>, start: &BytesStart) -> quick_xml::Result<Element> {
+    let mut element = Element::new(reader.decode(start.name())?);
+    for attr in start.attributes() {
+        let attr = attr?;
+        element.attributes.push((
+            reader.decode(attr.key)?.to_string(),
+            attr.unescape_and_decode_value(reader)?,
+        ));
+    }
+    Ok(element)
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_round_trip() {
+        let source = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!-- a comment -->
+<Root Version="2.0" Unknown="a &amp; b">
+    <Known ID="1">Fish &lt;&gt; chips</Known>
+    <Mystery><Deep Flag="Yes"/></Mystery>
+    <Notes><![CDATA[{\rtf1 <raw>}]]></Notes>
+</Root>
+"#;
+        let doc = Document::parse(source.as_bytes()).unwrap();
+        assert_eq!(doc.prolog.len(), 4);
+        assert_eq!(doc.root.attribute("Unknown"), Some("a & b"));
+        assert_eq!(doc.root.child("Known").unwrap().text(), "Fish <> chips");
+        assert_eq!(
+            doc.root
+                .child("Mystery")
+                .and_then(|m| m.child("Deep"))
+                .and_then(|d| d.attribute("Flag")),
+            Some("Yes")
+        );
+        assert_eq!(doc.root.child("Notes").unwrap().text(), r"{\rtf1 <raw>}");
+
+        let bytes = doc.to_bytes().unwrap();
+        assert_eq!(String::from_utf8(bytes.clone()).unwrap(), source);
+        assert_eq!(Document::parse(&bytes[..]).unwrap(), doc);
+    }
+
+    #[test]
+    pub fn test_edit_attributes() {
+        let mut element = Element::new("Item");
+        element.set_attribute("A", "1");
+        element.set_attribute("B", "2");
+        element.set_attribute("A", "3");
+        assert_eq!(
+            element.attributes,
+            vec![
+                ("A".to_string(), "3".to_string()),
+                ("B".to_string(), "2".to_string())
+            ]
+        );
+        assert_eq!(element.remove_attribute("A"), Some("3".to_string()));
+        assert_eq!(element.attribute("A"), None);
+    }
+}